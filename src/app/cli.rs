@@ -0,0 +1,109 @@
+use crate::app::error::AppError;
+use crate::app::App;
+use crate::boxworld::BoxWorld;
+use crate::game::camera::Camera;
+use crate::game::config::{Config, ConfigError};
+use crate::game::headless::HeadlessGame;
+use crate::game::systems::FixedTime;
+use crate::renderer::game_renderer::RenderStats;
+use crate::renderer::plugins::RendererPlugin;
+use crate::renderer::screenshot;
+use crate::ui::plugin::UIPlugin;
+use clap::Parser;
+use std::path::PathBuf;
+use winit::dpi::PhysicalSize;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Settings file, the defaults are used when it does not exist
+    #[arg(long, default_value = Config::PATH)]
+    pub config: PathBuf,
+
+    /// World generation seed, ignored when the world directory already has one
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Directory the chunks are loaded from and saved to
+    #[arg(long)]
+    pub world: Option<PathBuf>,
+
+    /// Number of chunks loaded around the camera
+    #[arg(long)]
+    pub render_distance: Option<usize>,
+
+    /// Runs the simulation without opening a window
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Number of fixed ticks to simulate in headless mode
+    #[arg(long, requires = "headless", default_value_t = 0)]
+    pub ticks: u64,
+
+    /// Renders the world offscreen to this PNG once the ticks have run
+    #[arg(long, requires = "headless")]
+    pub screenshot: Option<PathBuf>,
+}
+
+impl Cli {
+    // Settings file values overridden by the flags given on the command line
+    pub fn load_config(&self) -> Result<Config, ConfigError> {
+        let mut config = Config::load(&self.config)?;
+        if let Some(seed) = self.seed {
+            config.seed = seed;
+        }
+        if let Some(world) = &self.world {
+            config.world_path = Some(world.clone());
+        }
+        if let Some(render_distance) = self.render_distance {
+            config.render_distance = render_distance;
+        }
+        Ok(config)
+    }
+
+    pub fn run_headless(&self, config: Config) -> Result<(), AppError> {
+        let size = PhysicalSize::new(config.window_width, config.window_height);
+        let mut app = App::with_default_plugins()
+            .insert_resource(config)
+            .remove_plugin(RendererPlugin::LABEL)
+            .remove_plugin(UIPlugin::LABEL);
+        if self.screenshot.is_some() {
+            app = app.add_plugin(RendererPlugin::offscreen(size));
+        }
+
+        let mut game = HeadlessGame::with_app(app)?;
+        let step = game.world().resource::<FixedTime>().step();
+        game.run_ticks(self.ticks, step);
+        game.wait_for_chunks();
+
+        let box_world = game.world().resource::<BoxWorld>();
+        log::info!(
+            "Ran {} ticks with seed {}, camera at {}, {} chunks loaded",
+            game.get_ticks(),
+            box_world.get_seed(),
+            game.world().resource::<Camera>().position,
+            box_world.get_loaded_chunk_count()
+        );
+
+        if let Some(path) = &self.screenshot {
+            let image = game.screenshot();
+            let render_stats = game.world().resource::<RenderStats>();
+            log::info!(
+                "Drew {} chunks with {} faces, culled {} chunks, hid {} sections",
+                render_stats.drawn_chunks,
+                render_stats.drawn_faces,
+                render_stats.culled_chunks,
+                render_stats.hidden_sections
+            );
+            match screenshot::save(&image, path) {
+                Ok(_) => log::info!("Screenshot saved to {}", path.display()),
+                Err(err) => log::error!("Failed to save screenshot: {:?}", err),
+            }
+        }
+
+        game.world_mut()
+            .resource_mut::<BoxWorld>()
+            .save_modified_chunks();
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+use crate::boxworld::registry::RegistryError;
+use crate::boxworld::WorldError;
+
+#[derive(Debug, PartialEq)]
+pub enum AppError {
+    DuplicatePlugin(&'static str),
+    MissingDependency {
+        plugin: &'static str,
+        dependency: &'static str,
+    },
+    DependencyCycle(Vec<&'static str>),
+    MissingResource {
+        plugin: &'static str,
+        resource: &'static str,
+    },
+    // The plugin draws to a window and the app was built without one
+    MissingWindow(&'static str),
+    // The blocks file could not be read
+    Registry(RegistryError),
+    // The world directory could not be opened or the world cannot be generated with the blocks
+    World(WorldError),
+}
@@ -31,6 +31,12 @@ pub struct InputManager {
 
 const DOUBLE_PRESS_MAX_INTERVAL: f32 = 300.0;
 
+impl Default for InputManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl InputManager {
     pub fn new() -> Self {
         Self {
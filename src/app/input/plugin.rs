@@ -1,3 +1,4 @@
+use crate::app::error::AppError;
 use crate::app::input::InputManager;
 
 use crate::app::input::systems::clear::clear;
@@ -8,12 +9,26 @@ use winit::window::Window;
 
 pub struct InputPlugin;
 
+impl InputPlugin {
+    pub const LABEL: &'static str = "input";
+}
+
 impl Plugin for InputPlugin {
-    fn register_init(&self, world: &mut World, _init_schedule: &mut Schedule, _window: &Window) {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn register_init(
+        &self,
+        world: &mut World,
+        _init_schedule: &mut Schedule,
+        _window: Option<&Window>,
+    ) -> Result<(), AppError> {
         world.insert_resource(InputManager::new());
+        Ok(())
     }
 
     fn register_runtime(&self, _world: &mut World, schedule: &mut Schedule) {
-        schedule.add_systems(clear.in_set(ScheduleStage::PreRender));
+        schedule.add_systems(clear.in_set(ScheduleStage::PostUpdate));
     }
 }
@@ -1 +1,302 @@
+pub mod cli;
+pub mod error;
 pub mod input;
+
+use crate::app::error::AppError;
+use crate::app::input::plugin::InputPlugin;
+use crate::boxworld::plugin::WorldPlugin;
+use crate::game::camera::CameraPlugin;
+use crate::game::config::Config;
+use crate::game::player::PlayerPlugin;
+use crate::game::schedule::ScheduleStage;
+use crate::game::systems::{FixedTime, Time};
+use crate::physic::plugin::PhysicsPlugin;
+use crate::plugin::Plugin;
+use crate::renderer::plugins::RendererPlugin;
+use crate::ui::plugin::UIPlugin;
+use crate::worker::plugins::WorkerPlugin;
+use bevy_ecs::prelude::*;
+use winit::window::Window;
+
+pub struct App {
+    world: World,
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+pub struct Runtime {
+    pub world: World,
+    // Runs at the fixed tick rate
+    pub tick_schedule: Schedule,
+    // Runs once per rendered frame
+    pub frame_schedule: Schedule,
+}
+
+impl App {
+    pub fn new() -> Self {
+        let mut world = World::new();
+        world.insert_resource(Config::default());
+
+        Self {
+            world,
+            plugins: Vec::new(),
+        }
+    }
+
+    pub fn with_default_plugins() -> Self {
+        Self::new()
+            .add_plugin(InputPlugin)
+            .add_plugin(PhysicsPlugin)
+            .add_plugin(CameraPlugin)
+            .add_plugin(PlayerPlugin)
+            .add_plugin(RendererPlugin::default())
+            .add_plugin(WorldPlugin)
+            .add_plugin(UIPlugin)
+            .add_plugin(WorkerPlugin)
+    }
+
+    pub fn add_plugin<P: Plugin + 'static>(mut self, plugin: P) -> Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    pub fn remove_plugin(mut self, label: &str) -> Self {
+        self.plugins.retain(|plugin| plugin.label() != label);
+        self
+    }
+
+    pub fn has_plugin(&self, label: &str) -> bool {
+        self.plugins.iter().any(|plugin| plugin.label() == label)
+    }
+
+    pub fn insert_resource<R: Resource>(mut self, resource: R) -> Self {
+        self.world.insert_resource(resource);
+        self
+    }
+
+    pub fn resource<R: Resource>(&self) -> &R {
+        self.world.resource::<R>()
+    }
+
+    pub fn build(self, window: Option<&Window>) -> Result<Runtime, AppError> {
+        let Self { mut world, plugins } = self;
+        let plugins = sort_plugins(plugins)?;
+
+        let ticks_per_second = world.resource::<Config>().ticks_per_second;
+        world.insert_resource(Time::new());
+        world.insert_resource(FixedTime::from_ticks_per_second(ticks_per_second));
+
+        let mut init_schedule = Schedule::default();
+        for plugin in plugins.iter() {
+            plugin.register_init(&mut world, &mut init_schedule, window)?;
+        }
+        init_schedule.run(&mut world);
+
+        for plugin in plugins.iter() {
+            for resource in plugin.required_resources() {
+                if !resource.is_present(&world) {
+                    return Err(AppError::MissingResource {
+                        plugin: plugin.label(),
+                        resource: resource.name,
+                    });
+                }
+            }
+        }
+
+        let mut tick_schedule = Schedule::default();
+        let mut frame_schedule = Schedule::default();
+        for plugin in plugins.iter() {
+            plugin.register_runtime(&mut world, &mut tick_schedule);
+            plugin.register_frame(&mut world, &mut frame_schedule);
+        }
+        tick_schedule.configure_sets(
+            (
+                ScheduleStage::PreUpdate,
+                ScheduleStage::Update,
+                ScheduleStage::PostUpdate,
+            )
+                .chain(),
+        );
+        frame_schedule.configure_sets((ScheduleStage::PreRender, ScheduleStage::Render).chain());
+
+        log::info!(
+            "Plugins: {:?}",
+            plugins
+                .iter()
+                .map(|plugin| plugin.label())
+                .collect::<Vec<_>>()
+        );
+
+        Ok(Runtime {
+            world,
+            tick_schedule,
+            frame_schedule,
+        })
+    }
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Orders the plugins so every plugin comes after its dependencies, otherwise keeping the order
+// they were added in
+fn sort_plugins(mut plugins: Vec<Box<dyn Plugin>>) -> Result<Vec<Box<dyn Plugin>>, AppError> {
+    for (i, plugin) in plugins.iter().enumerate() {
+        if plugins[..i]
+            .iter()
+            .any(|other| other.label() == plugin.label())
+        {
+            return Err(AppError::DuplicatePlugin(plugin.label()));
+        }
+    }
+
+    for plugin in plugins.iter() {
+        for dependency in plugin.dependencies() {
+            if !plugins.iter().any(|other| other.label() == *dependency) {
+                return Err(AppError::MissingDependency {
+                    plugin: plugin.label(),
+                    dependency,
+                });
+            }
+        }
+    }
+
+    let mut sorted: Vec<Box<dyn Plugin>> = Vec::with_capacity(plugins.len());
+    while !plugins.is_empty() {
+        let ready = plugins.iter().position(|plugin| {
+            plugin
+                .dependencies()
+                .iter()
+                .all(|dependency| sorted.iter().any(|other| other.label() == *dependency))
+        });
+        match ready {
+            Some(index) => sorted.push(plugins.remove(index)),
+            None => {
+                return Err(AppError::DependencyCycle(
+                    plugins.iter().map(|plugin| plugin.label()).collect(),
+                ))
+            }
+        }
+    }
+
+    Ok(sorted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::App;
+    use crate::app::error::AppError;
+    use crate::plugin::{Plugin, RequiredResource};
+    use bevy_ecs::prelude::*;
+    use winit::window::Window;
+
+    #[derive(Resource, Default)]
+    struct Order(Vec<&'static str>);
+
+    #[derive(Resource)]
+    struct Unused;
+
+    struct TestPlugin {
+        label: &'static str,
+        dependencies: &'static [&'static str],
+        requires_unused: bool,
+    }
+
+    impl TestPlugin {
+        fn new(label: &'static str, dependencies: &'static [&'static str]) -> Self {
+            Self {
+                label,
+                dependencies,
+                requires_unused: false,
+            }
+        }
+    }
+
+    impl Plugin for TestPlugin {
+        fn label(&self) -> &'static str {
+            self.label
+        }
+
+        fn dependencies(&self) -> &'static [&'static str] {
+            self.dependencies
+        }
+
+        fn required_resources(&self) -> Vec<RequiredResource> {
+            if self.requires_unused {
+                vec![RequiredResource::of::<Unused>()]
+            } else {
+                Vec::new()
+            }
+        }
+
+        fn register_init(
+            &self,
+            world: &mut World,
+            _schedule: &mut Schedule,
+            _window: Option<&Window>,
+        ) -> Result<(), AppError> {
+            world.resource_mut::<Order>().0.push(self.label);
+            Ok(())
+        }
+    }
+
+    fn build(app: App) -> Result<Vec<&'static str>, AppError> {
+        let runtime = app.insert_resource(Order::default()).build(None)?;
+        Ok(runtime.world.resource::<Order>().0.clone())
+    }
+
+    #[test]
+    fn dependencies_are_initialized_first() {
+        let app = App::new()
+            .add_plugin(TestPlugin::new("c", &["b"]))
+            .add_plugin(TestPlugin::new("a", &[]))
+            .add_plugin(TestPlugin::new("b", &["a"]))
+            .add_plugin(TestPlugin::new("d", &[]));
+
+        assert_eq!(build(app), Ok(vec!["a", "b", "c", "d"]));
+    }
+
+    #[test]
+    fn removed_plugin_is_not_initialized() {
+        let app = App::new()
+            .add_plugin(TestPlugin::new("a", &[]))
+            .add_plugin(TestPlugin::new("b", &[]))
+            .remove_plugin("a");
+
+        assert!(!app.has_plugin("a"));
+        assert_eq!(build(app), Ok(vec!["b"]));
+    }
+
+    #[test]
+    fn invalid_plugins_are_rejected() {
+        let missing = App::new().add_plugin(TestPlugin::new("a", &["b"]));
+        assert_eq!(
+            build(missing),
+            Err(AppError::MissingDependency {
+                plugin: "a",
+                dependency: "b"
+            })
+        );
+
+        let duplicate = App::new()
+            .add_plugin(TestPlugin::new("a", &[]))
+            .add_plugin(TestPlugin::new("a", &[]));
+        assert_eq!(build(duplicate), Err(AppError::DuplicatePlugin("a")));
+
+        let cycle = App::new()
+            .add_plugin(TestPlugin::new("a", &["b"]))
+            .add_plugin(TestPlugin::new("b", &["a"]));
+        assert_eq!(build(cycle), Err(AppError::DependencyCycle(vec!["a", "b"])));
+
+        let resource = App::new().add_plugin(TestPlugin {
+            requires_unused: true,
+            ..TestPlugin::new("a", &[])
+        });
+        assert!(matches!(
+            build(resource),
+            Err(AppError::MissingResource { plugin: "a", .. })
+        ));
+    }
+}
@@ -1,7 +1,8 @@
-use crate::game::common::transform::Transform;
+use crate::boxworld::model::{ModelUv, FACE_ORIENTATIONS, MODEL_SIZE};
+use crate::boxworld::registry::BlockId;
+use crate::boxworld::state::BlockState;
 use bitflags::bitflags;
-use nalgebra::{Matrix4, Vector2};
-use std::time::Duration;
+use nalgebra::{Vector2, Vector3};
 
 bitflags! {
     #[derive(Clone, PartialEq, Debug)]
@@ -15,10 +16,12 @@ bitflags! {
     }
 }
 
-#[derive(Clone)]
+// What the block is is looked up in the BlockRegistry with its id, the state tells how it is
+// placed
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Block {
-    pub r#type: BlockType,
-    pub face: BlockFace,
+    pub id: BlockId,
+    pub state: BlockState,
 }
 
 impl Block {
@@ -27,42 +30,62 @@ impl Block {
 
     pub const TOTAL_FACES: usize = 6;
 
-    pub fn new(r#type: BlockType) -> Self {
-        Self {
-            r#type,
-            face: BlockFace::empty(),
-        }
+    pub fn new(id: BlockId) -> Self {
+        Self::with_state(id, BlockState::default())
     }
 
-    pub fn get_texture_pos(r#type: BlockType, face: BlockFace) -> Vector2<i32> {
-        match r#type {
-            BlockType::Dirt => {
-                if face == BlockFace::TOP {
-                    Vector2::new(0, 0)
-                } else if face == BlockFace::BOTTOM {
-                    Vector2::new(2, 0)
-                } else {
-                    Vector2::new(1, 0)
-                }
-            }
-            _ => unreachable!(), // BlockType::Grass => {}
-                                 // BlockType::Cobblestone => {}
-        }
+    pub fn with_state(id: BlockId, state: BlockState) -> Self {
+        Self { id, state }
     }
 }
 
-// #[warn(dead_code)]
-#[derive(Clone)]
+// Where a packed face lies, to draw the translucent ones from the farthest
+pub trait FaceCenter {
+    // From the chunk origin, in blocks
+    fn get_center(&self) -> Vector3<f32>;
+}
+
+// One or more coplanar block faces packed in 8 bytes, decoded by vertex.vert. The position is
+// the lowest block of the quad inside its chunk, the chunk origin is bound per draw.
+// First word: x 4 bits, y 7, z 4, face 3, width - 1 4, height - 1 4, light 4
+// Second word: texture x 8 bits, texture y 8, texture quarter turns 2
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
 pub struct RawFaceInstance {
-    model_transformation: Matrix4<f32>,
-    texture_pos: Vector2<i32>,
+    data: [u32; 2],
 }
 
 impl RawFaceInstance {
-    pub fn from(r#type: BlockType, face: BlockFace, transform: &Transform) -> Self {
+    pub const MAX_LIGHT: u8 = 15;
+    pub const MAX_SIZE: usize = 16;
+
+    pub fn new(
+        position: Vector3<usize>,
+        face: BlockFace,
+        size: Vector2<usize>,
+        texture_pos: Vector2<i32>,
+        texture_turns: u8,
+        light: u8,
+    ) -> Self {
+        debug_assert!(position.x < 16 && position.y < 128 && position.z < 16);
+        debug_assert!((1..=Self::MAX_SIZE).contains(&size.x));
+        debug_assert!((1..=Self::MAX_SIZE).contains(&size.y));
+        debug_assert!(light <= Self::MAX_LIGHT);
+
+        let face_index = face.bits().trailing_zeros();
         Self {
-            model_transformation: transform.get_transformation_matrix(),
-            texture_pos: Block::get_texture_pos(r#type, face),
+            data: [
+                position.x as u32
+                    | (position.y as u32) << 4
+                    | (position.z as u32) << 11
+                    | face_index << 15
+                    | (size.x as u32 - 1) << 18
+                    | (size.y as u32 - 1) << 22
+                    | (light as u32) << 26,
+                (texture_pos.x as u32 & 0xFF)
+                    | (texture_pos.y as u32 & 0xFF) << 8
+                    | (texture_turns as u32 & 0x3) << 16,
+            ],
         }
     }
 
@@ -71,43 +94,281 @@ impl RawFaceInstance {
         wgpu::VertexBufferLayout {
             array_stride: mem::size_of::<RawFaceInstance>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 10,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 11,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 12,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 13,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
-                    shader_location: 14,
-                    format: wgpu::VertexFormat::Sint32x2,
-                },
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 10,
+                format: wgpu::VertexFormat::Uint32x2,
+            }],
+        }
+    }
+
+    pub fn get_position(&self) -> Vector3<usize> {
+        Vector3::new(
+            (self.data[0] & 0xF) as usize,
+            (self.data[0] >> 4 & 0x7F) as usize,
+            (self.data[0] >> 11 & 0xF) as usize,
+        )
+    }
+
+    pub fn get_size(&self) -> Vector2<usize> {
+        Vector2::new(
+            (self.data[0] >> 18 & 0xF) as usize + 1,
+            (self.data[0] >> 22 & 0xF) as usize + 1,
+        )
+    }
+}
+
+impl FaceCenter for RawFaceInstance {
+    // The quad starts at the lowest block and lies on the outer side of the blocks, as in
+    // vertex.vert
+    fn get_center(&self) -> Vector3<f32> {
+        let (_, right, up) = FACE_ORIENTATIONS[(self.data[0] >> 15 & 0x7) as usize];
+        let right = Vector3::from(right).cast::<f32>();
+        let up = Vector3::from(up).cast::<f32>();
+        let size = self.get_size().cast::<f32>();
+        self.get_position().cast::<f32>()
+            + right.abs() * size.x * 0.5
+            + up.abs() * size.y * 0.5
+            + right.cross(&up).sup(&Vector3::zeros())
+    }
+}
+
+// The rest of the decoding is done by vertex.vert, only the tests look at it
+#[cfg(test)]
+impl RawFaceInstance {
+    pub fn get_face(&self) -> BlockFace {
+        BlockFace::from_bits_truncate(1 << (self.data[0] >> 15 & 0x7))
+    }
+
+    pub fn get_texture_pos(&self) -> Vector2<i32> {
+        Vector2::new(
+            (self.data[1] & 0xFF) as i32,
+            (self.data[1] >> 8 & 0xFF) as i32,
+        )
+    }
+
+    pub fn get_texture_turns(&self) -> u8 {
+        (self.data[1] >> 16 & 0x3) as u8
+    }
+
+    pub fn get_light(&self) -> u8 {
+        (self.data[0] >> 26 & 0xF) as u8
+    }
+}
+
+// Quad of a block model packed in 16 bytes, decoded by model.vert. The quad is a parallelogram
+// starting at the origin, in sixteenths of a block from the block position inside its chunk, and
+// spanning the right and up vectors. The uv is in sixteenths of the texture tile.
+// First word: x 4 bits, y 7, z 4, light 4
+// Second word: origin x 5 bits, origin y 5, origin z 5, texture x 8, texture y 8
+// Third word: right x + 16 6 bits, right y + 16 6, right z + 16 6, uv left 5, uv top 5
+// Fourth word: up x + 16 6 bits, up y + 16 6, up z + 16 6, uv right 5, uv bottom 5
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct RawModelFaceInstance {
+    data: [u32; 4],
+}
+
+impl RawModelFaceInstance {
+    pub const MAX_LIGHT: u8 = RawFaceInstance::MAX_LIGHT;
+
+    pub fn new(
+        position: Vector3<usize>,
+        origin: Vector3<u8>,
+        right: Vector3<i8>,
+        up: Vector3<i8>,
+        uv: ModelUv,
+        texture_pos: Vector2<i32>,
+        light: u8,
+    ) -> Self {
+        let size = MODEL_SIZE as i8;
+        debug_assert!(position.x < 16 && position.y < 128 && position.z < 16);
+        debug_assert!(origin.iter().all(|coord| *coord <= MODEL_SIZE));
+        debug_assert!(right
+            .iter()
+            .chain(up.iter())
+            .all(|coord| (-size..=size).contains(coord)));
+        debug_assert!([uv.0, uv.1, uv.2, uv.3]
+            .iter()
+            .all(|coord| *coord <= MODEL_SIZE));
+        debug_assert!(light <= Self::MAX_LIGHT);
+
+        let pack_vector = |v: Vector3<i8>| {
+            (v.x + size) as u32 | ((v.y + size) as u32) << 6 | ((v.z + size) as u32) << 12
+        };
+        Self {
+            data: [
+                position.x as u32
+                    | (position.y as u32) << 4
+                    | (position.z as u32) << 11
+                    | (light as u32) << 15,
+                origin.x as u32
+                    | (origin.y as u32) << 5
+                    | (origin.z as u32) << 10
+                    | (texture_pos.x as u32 & 0xFF) << 15
+                    | (texture_pos.y as u32 & 0xFF) << 23,
+                pack_vector(right) | (uv.0 as u32) << 18 | (uv.1 as u32) << 23,
+                pack_vector(up) | (uv.2 as u32) << 18 | (uv.3 as u32) << 23,
             ],
         }
     }
+
+    pub fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<RawModelFaceInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 10,
+                format: wgpu::VertexFormat::Uint32x4,
+            }],
+        }
+    }
+
+    fn unpack_vector(word: u32) -> Vector3<i8> {
+        let size = MODEL_SIZE as i8;
+        Vector3::new(
+            (word & 0x3F) as i8 - size,
+            (word >> 6 & 0x3F) as i8 - size,
+            (word >> 12 & 0x3F) as i8 - size,
+        )
+    }
+
+    pub fn get_position(&self) -> Vector3<usize> {
+        Vector3::new(
+            (self.data[0] & 0xF) as usize,
+            (self.data[0] >> 4 & 0x7F) as usize,
+            (self.data[0] >> 11 & 0xF) as usize,
+        )
+    }
+
+    pub fn get_origin(&self) -> Vector3<u8> {
+        Vector3::new(
+            (self.data[1] & 0x1F) as u8,
+            (self.data[1] >> 5 & 0x1F) as u8,
+            (self.data[1] >> 10 & 0x1F) as u8,
+        )
+    }
+
+    pub fn get_right(&self) -> Vector3<i8> {
+        Self::unpack_vector(self.data[2])
+    }
+
+    pub fn get_up(&self) -> Vector3<i8> {
+        Self::unpack_vector(self.data[3])
+    }
 }
 
-#[derive(Clone, Copy)]
-#[repr(u32)]
-pub enum BlockType {
-    Dirt,
-    Grass,
-    Cobblestone,
+impl FaceCenter for RawModelFaceInstance {
+    fn get_center(&self) -> Vector3<f32> {
+        let corner = self.get_origin().cast::<f32>()
+            + (self.get_right().cast::<f32>() + self.get_up().cast::<f32>()) * 0.5;
+        self.get_position().cast::<f32>() + corner / MODEL_SIZE as f32
+    }
+}
+
+// The rest of the decoding is done by model.vert, only the tests look at it
+#[cfg(test)]
+impl RawModelFaceInstance {
+    pub fn get_light(&self) -> u8 {
+        (self.data[0] >> 15 & 0xF) as u8
+    }
+
+    pub fn get_texture_pos(&self) -> Vector2<i32> {
+        Vector2::new(
+            (self.data[1] >> 15 & 0xFF) as i32,
+            (self.data[1] >> 23 & 0xFF) as i32,
+        )
+    }
+
+    pub fn get_uv(&self) -> ModelUv {
+        (
+            (self.data[2] >> 18 & 0x1F) as u8,
+            (self.data[2] >> 23 & 0x1F) as u8,
+            (self.data[3] >> 18 & 0x1F) as u8,
+            (self.data[3] >> 23 & 0x1F) as u8,
+        )
+    }
 }
 
-fn update_chunk(_block: &mut Block, _time_elapsed: &Duration) {}
+#[cfg(test)]
+mod test {
+    use super::{BlockFace, FaceCenter, RawFaceInstance, RawModelFaceInstance};
+    use nalgebra::{Vector2, Vector3};
+
+    #[test]
+    fn packed_face_is_decoded_back() {
+        let instance = RawFaceInstance::new(
+            Vector3::new(15, 127, 3),
+            BlockFace::BOTTOM,
+            Vector2::new(16, 1),
+            Vector2::new(2, 15),
+            3,
+            7,
+        );
+        assert_eq!(std::mem::size_of::<RawFaceInstance>(), 8);
+        assert_eq!(instance.get_position(), Vector3::new(15, 127, 3));
+        assert_eq!(instance.get_face(), BlockFace::BOTTOM);
+        assert_eq!(instance.get_size(), Vector2::new(16, 1));
+        assert_eq!(instance.get_texture_pos(), Vector2::new(2, 15));
+        assert_eq!(instance.get_texture_turns(), 3);
+        assert_eq!(instance.get_light(), 7);
+    }
+
+    #[test]
+    fn packed_model_face_is_decoded_back() {
+        let instance = RawModelFaceInstance::new(
+            Vector3::new(15, 127, 3),
+            Vector3::new(16, 0, 8),
+            Vector3::new(-16, 0, 16),
+            Vector3::new(0, 16, -3),
+            (0, 2, 16, 14),
+            Vector2::new(2, 15),
+            7,
+        );
+        assert_eq!(std::mem::size_of::<RawModelFaceInstance>(), 16);
+        assert_eq!(instance.get_position(), Vector3::new(15, 127, 3));
+        assert_eq!(instance.get_origin(), Vector3::new(16, 0, 8));
+        assert_eq!(instance.get_right(), Vector3::new(-16, 0, 16));
+        assert_eq!(instance.get_up(), Vector3::new(0, 16, -3));
+        assert_eq!(instance.get_uv(), (0, 2, 16, 14));
+        assert_eq!(instance.get_texture_pos(), Vector2::new(2, 15));
+        assert_eq!(instance.get_light(), 7);
+    }
+
+    #[test]
+    fn face_center_is_in_the_middle_of_the_quad() {
+        // Three blocks along x and two along z, on top of the blocks
+        let top = RawFaceInstance::new(
+            Vector3::new(2, 3, 4),
+            BlockFace::TOP,
+            Vector2::new(3, 2),
+            Vector2::new(0, 0),
+            0,
+            0,
+        );
+        assert_eq!(top.get_center(), Vector3::new(3.5, 4.0, 5.0));
+        let back = RawFaceInstance::new(
+            Vector3::new(2, 3, 4),
+            BlockFace::BACK,
+            Vector2::new(1, 1),
+            Vector2::new(0, 0),
+            0,
+            0,
+        );
+        assert_eq!(back.get_center(), Vector3::new(2.5, 3.5, 4.0));
+
+        // Across the middle of the block
+        let quad = RawModelFaceInstance::new(
+            Vector3::new(1, 2, 3),
+            Vector3::new(0, 0, 8),
+            Vector3::new(16, 0, 0),
+            Vector3::new(0, 16, 0),
+            (0, 0, 16, 16),
+            Vector2::new(0, 0),
+            0,
+        );
+        assert_eq!(quad.get_center(), Vector3::new(1.5, 2.5, 3.5));
+    }
+}
@@ -1,17 +1,36 @@
 use crate::boxworld::block::{Block, RawFaceInstance};
-use crate::boxworld::BoxWorld;
-use crate::game::common::transform::Transform;
+use crate::boxworld::mesher::{get_cell_index, greedy_mesh, SectionFaces, SectionMesh};
+use crate::boxworld::model::BlockModel;
+use crate::boxworld::position::{ChunkPos, LocalPos};
+use crate::boxworld::registry::{BlockId, BlockRegistry, RenderLayer};
+use crate::boxworld::section::ChunkSection;
+use crate::boxworld::tick::ScheduledTick;
 use bevy_ecs::prelude::Resource;
-use nalgebra::{Rotation3, Translation3, Vector2, Vector3};
+use nalgebra::Vector3;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
 
 use super::block::BlockFace;
 
+// Loaded chunks around a chunk, named after the face of the chunk they touch
+#[derive(Default, Clone, Copy)]
+pub struct ChunkNeighbours<'a> {
+    pub left: Option<&'a Chunk>,
+    pub right: Option<&'a Chunk>,
+    pub back: Option<&'a Chunk>,
+    pub front: Option<&'a Chunk>,
+}
+
 #[derive(Clone, Resource)]
 pub struct Chunk {
-    blocks: Vec<Vec<Vec<Option<Block>>>>,
-    chunk_coord: Vector2<i32>,
-    world_coord: Vector3<f32>,
-    raw_face_instances: Vec<RawFaceInstance>,
+    sections: Vec<ChunkSection>,
+    chunk_coord: ChunkPos,
+    // Side in blocks of the cubes the chunk is meshed with, 1 for full resolution
+    lod: usize,
+    // Earliest first
+    scheduled_ticks: BinaryHeap<Reverse<ScheduledTick>>,
+    // The blocks in scheduled_ticks, to find the ones already waiting without going through it
+    scheduled_blocks: HashSet<(LocalPos, BlockId)>,
 }
 
 impl Chunk {
@@ -19,249 +38,540 @@ impl Chunk {
     pub const CHUNK_VERTICAL_BLOCK: usize = 128;
     pub const MAXIMUM_TOTAL_BLOCKS: usize =
         Self::CHUNK_SIDE_BLOCK * Self::CHUNK_SIDE_BLOCK * Self::CHUNK_VERTICAL_BLOCK;
+    pub const SECTION_COUNT: usize = Self::CHUNK_VERTICAL_BLOCK / ChunkSection::SIZE;
 
     pub const CHUNK_SIDE_SIZE: f32 = Self::CHUNK_SIDE_BLOCK as f32 * Block::SIZE;
-    pub const CHUNK_HALF_SIDE_SIZE: f32 = Self::CHUNK_SIDE_SIZE * 0.5;
-
-    pub fn with_block(block: Option<Block>, chunk_coord: Vector2<i32>) -> Self {
-        let blocks = vec![
-            vec![vec![block; Self::CHUNK_SIDE_BLOCK]; Self::CHUNK_VERTICAL_BLOCK];
-            Self::CHUNK_SIDE_BLOCK
-        ];
-        Self::with_blocks(blocks, chunk_coord)
+
+    // Only air, the faces are calculated once the blocks are set and update_mesh is called
+    pub fn new(chunk_coord: ChunkPos) -> Self {
+        Self::with_sections(vec![ChunkSection::new(); Self::SECTION_COUNT], chunk_coord)
+    }
+
+    pub fn with_sections(sections: Vec<ChunkSection>, chunk_coord: ChunkPos) -> Self {
+        debug_assert_eq!(sections.len(), Self::SECTION_COUNT);
+        Self {
+            sections,
+            chunk_coord,
+            lod: 1,
+            scheduled_ticks: BinaryHeap::new(),
+            scheduled_blocks: HashSet::new(),
+        }
     }
 
-    pub fn get_chunk_coord(&self) -> &Vector2<i32> {
+    pub fn get_chunk_coord(&self) -> &ChunkPos {
         &self.chunk_coord
     }
 
-    pub fn with_blocks(blocks: Vec<Vec<Vec<Option<Block>>>>, chunk_coord: Vector2<i32>) -> Self {
-        let world_coord_xz = BoxWorld::get_world_coord_from_chunk_coord(&chunk_coord);
-        let mut instance = Self {
-            blocks,
-            raw_face_instances: Vec::with_capacity(Self::MAXIMUM_TOTAL_BLOCKS),
-            world_coord: Vector3::new(world_coord_xz.x, 0.0, world_coord_xz.y),
-            chunk_coord,
+    pub fn get_sections(&self) -> &Vec<ChunkSection> {
+        &self.sections
+    }
+
+    pub fn get_lod(&self) -> usize {
+        self.lod
+    }
+
+    // The whole chunk is remeshed at the new resolution
+    pub fn set_lod(&mut self, lod: usize) {
+        debug_assert!(lod.is_power_of_two() && lod <= ChunkSection::SIZE);
+        if self.lod != lod {
+            self.lod = lod;
+            self.mark_dirty();
+        }
+    }
+
+    #[inline]
+    pub fn get_block(&self, pos: LocalPos) -> Option<Block> {
+        self.sections[pos.y / ChunkSection::SIZE].get_block(
+            pos.x,
+            pos.y % ChunkSection::SIZE,
+            pos.z,
+        )
+    }
+
+    // Block standing for the cube of `scale` blocks on each side at the cell coordinate: the
+    // highest cube, so the surface keeps its texture from afar. Smaller shapes are left out.
+    pub fn get_lod_block(
+        &self,
+        registry: &BlockRegistry,
+        scale: usize,
+        x: usize,
+        y: usize,
+        z: usize,
+    ) -> Option<Block> {
+        if scale == 1 {
+            return self.get_block(LocalPos::new(x, y, z));
+        }
+        let (x, y, z) = (x * scale, y * scale, z * scale);
+        let get_cube = |x, y, z| {
+            self.get_block(LocalPos::new(x, y, z))
+                .filter(|block| registry.get_model(block.id).is_cube())
+        };
+        (y..y + scale).rev().find_map(|y| {
+            (z..z + scale).find_map(|z| (x..x + scale).find_map(|x| get_cube(x, y, z)))
+        })
+    }
+
+    // Marks the section dirty, along with the vertical neighbour whose faces the block touches
+    pub fn set_block(&mut self, pos: LocalPos, block: Option<Block>) {
+        let section_y = pos.y / ChunkSection::SIZE;
+        let local_y = pos.y % ChunkSection::SIZE;
+        self.sections[section_y].set_block(pos.x, local_y, pos.z, block);
+
+        if local_y == 0 && section_y > 0 {
+            self.sections[section_y - 1].mark_dirty();
+        } else if local_y == ChunkSection::SIZE - 1 && section_y + 1 < Self::SECTION_COUNT {
+            self.sections[section_y + 1].mark_dirty();
+        }
+    }
+
+    // Ignored when the block already waits for an update
+    pub fn schedule_tick(&mut self, scheduled: ScheduledTick) {
+        if self.scheduled_blocks.insert((scheduled.pos, scheduled.id)) {
+            self.scheduled_ticks.push(Reverse(scheduled));
+        }
+    }
+
+    // Scheduled ticks due by `tick`, the earliest first
+    pub fn take_due_ticks(&mut self, tick: u64) -> Vec<ScheduledTick> {
+        let mut due_ticks = Vec::new();
+        while let Some(Reverse(scheduled)) = self.scheduled_ticks.peek() {
+            if scheduled.tick > tick {
+                break;
+            }
+            let scheduled = self.scheduled_ticks.pop().unwrap().0;
+            self.scheduled_blocks.remove(&(scheduled.pos, scheduled.id));
+            due_ticks.push(scheduled);
+        }
+        due_ticks
+    }
+
+    // Earliest first
+    pub fn get_scheduled_ticks(&self) -> Vec<ScheduledTick> {
+        let mut scheduled_ticks = self
+            .scheduled_ticks
+            .iter()
+            .map(|Reverse(scheduled)| *scheduled)
+            .collect::<Vec<ScheduledTick>>();
+        scheduled_ticks.sort();
+        scheduled_ticks
+    }
+
+    pub fn mark_section_dirty(&mut self, y: usize) {
+        self.sections[y / ChunkSection::SIZE].mark_dirty();
+    }
+
+    pub fn mark_dirty(&mut self) {
+        for section in self.sections.iter_mut() {
+            section.mark_dirty();
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.sections.iter().any(|section| section.is_dirty())
+    }
+
+    // Faces of the cell not covered by a neighbour, the coordinates are in cells of `scale`
+    // blocks. Across the chunk borders the neighbouring chunks are looked at with the same
+    // scale, and faces toward a chunk that is not loaded are visible. A face is covered as told by
+    // BlockRegistry::is_face_hidden.
+    fn get_face(
+        &self,
+        neighbours: &ChunkNeighbours,
+        registry: &BlockRegistry,
+        scale: usize,
+        x: usize,
+        y: usize,
+        z: usize,
+    ) -> BlockFace {
+        let mut face = BlockFace::empty();
+        let Some(block) = self.get_lod_block(registry, scale, x, y, z) else {
+            return face;
+        };
+        let last = Self::CHUNK_SIDE_BLOCK / scale - 1;
+        // `side` is the side of the neighbour touching the block
+        let is_uncovered =
+            |chunk: Option<&Chunk>, x: usize, y: usize, z: usize, side: BlockFace| {
+                chunk.is_none_or(|chunk| {
+                    chunk
+                        .get_lod_block(registry, scale, x, y, z)
+                        .is_none_or(|other| !registry.is_face_hidden(&block, &other, &side))
+                })
+            };
+
+        // X
+        if x == last {
+            if is_uncovered(neighbours.right, 0, y, z, BlockFace::LEFT) {
+                face |= BlockFace::RIGHT;
+            }
+        } else if is_uncovered(Some(self), x + 1, y, z, BlockFace::LEFT) {
+            face |= BlockFace::RIGHT;
+        }
+        if x == 0 {
+            if is_uncovered(neighbours.left, last, y, z, BlockFace::RIGHT) {
+                face |= BlockFace::LEFT;
+            }
+        } else if is_uncovered(Some(self), x - 1, y, z, BlockFace::RIGHT) {
+            face |= BlockFace::LEFT;
+        }
+
+        // Y
+        if y + 1 >= Self::CHUNK_VERTICAL_BLOCK / scale
+            || is_uncovered(Some(self), x, y + 1, z, BlockFace::BOTTOM)
+        {
+            face |= BlockFace::TOP;
+        }
+        if y == 0 || is_uncovered(Some(self), x, y - 1, z, BlockFace::TOP) {
+            face |= BlockFace::BOTTOM;
+        }
+
+        // Z
+        if z == last {
+            if is_uncovered(neighbours.front, x, y, 0, BlockFace::BACK) {
+                face |= BlockFace::FRONT;
+            }
+        } else if is_uncovered(Some(self), x, y, z + 1, BlockFace::BACK) {
+            face |= BlockFace::FRONT;
+        }
+        if z == 0 {
+            if is_uncovered(neighbours.back, x, y, last, BlockFace::FRONT) {
+                face |= BlockFace::BACK;
+            }
+        } else if is_uncovered(Some(self), x, y, z - 1, BlockFace::FRONT) {
+            face |= BlockFace::BACK;
+        }
+
+        face
+    }
+
+    // A solid section hidden on every side by sections full of opaque blocks has no visible face
+    fn is_section_occluded(
+        &self,
+        neighbours: &ChunkNeighbours,
+        section_y: usize,
+        registry: &BlockRegistry,
+    ) -> bool {
+        let is_solid = |chunk: Option<&Chunk>, section_y: Option<usize>| {
+            chunk
+                .zip(section_y)
+                .and_then(|(chunk, section_y)| chunk.sections.get(section_y))
+                .filter(|section| section.is_solid())
+                .and_then(|section| section.get_block(0, 0, 0))
+                .is_some_and(|block| registry.is_opaque(block.id))
         };
-        instance.calculate_faces();
-        instance.calculate_raw_face_instances();
-        instance
+
+        is_solid(Some(self), Some(section_y))
+            && is_solid(Some(self), section_y.checked_sub(1))
+            && is_solid(Some(self), Some(section_y + 1))
+            && is_solid(neighbours.left, Some(section_y))
+            && is_solid(neighbours.right, Some(section_y))
+            && is_solid(neighbours.back, Some(section_y))
+            && is_solid(neighbours.front, Some(section_y))
+    }
+
+    // Remeshes the dirty sections only
+    pub fn update_mesh(&mut self, neighbours: &ChunkNeighbours, registry: &BlockRegistry) {
+        for section_y in 0..Self::SECTION_COUNT {
+            if self.sections[section_y].is_dirty() {
+                let (mesh, translucent_mesh) =
+                    self.calculate_section_meshes(neighbours, section_y, registry);
+                self.sections[section_y].set_mesh(mesh, translucent_mesh, registry);
+            }
+        }
     }
 
-    fn calculate_faces(&mut self) {
-        for x in 0..Self::CHUNK_SIDE_BLOCK {
-            for y in 0..Self::CHUNK_VERTICAL_BLOCK {
-                for z in 0..Self::CHUNK_SIDE_BLOCK {
-                    let mut face = BlockFace::empty();
+    fn is_same_block_above(&self, block: &Block, x: usize, y: usize, z: usize) -> bool {
+        y + 1 < Self::CHUNK_VERTICAL_BLOCK
+            && self
+                .get_block(LocalPos::new(x, y + 1, z))
+                .is_some_and(|above| above.id == block.id)
+    }
+
+    // Faces of the opaque and cutout blocks, then the ones of the translucent blocks
+    fn calculate_section_meshes(
+        &self,
+        neighbours: &ChunkNeighbours,
+        section_y: usize,
+        registry: &BlockRegistry,
+    ) -> (SectionMesh, SectionMesh) {
+        let mut mesh = SectionMesh::default();
+        let mut translucent_mesh = SectionMesh::default();
+        let section = &self.sections[section_y];
+        if section.is_empty() || self.is_section_occluded(neighbours, section_y, registry) {
+            return (mesh, translucent_mesh);
+        }
+        // The section is meshed as cells of lod blocks on each side
+        let scale = self.lod;
+        let cells = ChunkSection::SIZE / scale;
+        // The inside of a section of a single cube is covered on every side by the same block
+        let is_solid = section.is_solid()
+            && section
+                .get_block(0, 0, 0)
+                .is_some_and(|block| registry.get_model(block.id).is_cube());
+        let last = cells - 1;
+
+        let mut faces: SectionFaces = vec![None; cells * cells * cells];
+        let mut translucent_faces: SectionFaces = vec![None; cells * cells * cells];
+        let mut has_translucent = false;
+        let y_offset = section_y * cells;
+        for local_y in 0..cells {
+            for z in 0..cells {
+                for x in 0..cells {
+                    if is_solid
+                        && (1..last).contains(&x)
+                        && (1..last).contains(&local_y)
+                        && (1..last).contains(&z)
                     {
-                        let y_blocks = unsafe { self.blocks.get_unchecked(x) };
-                        let z_blocks = unsafe { y_blocks.get_unchecked(y) };
-                        let maybe_block = unsafe { z_blocks.get_unchecked(z) };
-
-                        if maybe_block.is_some() {
-                            // X
-                            {
-                                let _x = x + 1;
-                                if Self::CHUNK_SIDE_BLOCK <= _x
-                                    || unsafe {
-                                        self.blocks
-                                            .get_unchecked(_x)
-                                            .get_unchecked(y)
-                                            .get_unchecked(z)
-                                    }
-                                    .is_none()
-                                {
-                                    face |= BlockFace::RIGHT;
-                                }
-                            }
-                            {
-                                if x == 0
-                                    || unsafe {
-                                        self.blocks
-                                            .get_unchecked(x - 1)
-                                            .get_unchecked(y)
-                                            .get_unchecked(z)
-                                    }
-                                    .is_none()
-                                {
-                                    face |= BlockFace::LEFT;
-                                }
-                            }
-
-                            // Y
-                            {
-                                let _y = y + 1;
-                                if Self::CHUNK_VERTICAL_BLOCK <= _y
-                                    || unsafe { y_blocks.get_unchecked(_y).get_unchecked(z) }
-                                        .is_none()
-                                {
-                                    face |= BlockFace::TOP;
-                                }
-                            }
-                            {
-                                if y == 0
-                                    || unsafe { y_blocks.get_unchecked(y - 1).get_unchecked(z) }
-                                        .is_none()
-                                {
-                                    face |= BlockFace::BOTTOM;
-                                }
-                            }
-
-                            // Z
-                            {
-                                let _z = z + 1;
-                                if Self::CHUNK_SIDE_BLOCK <= _z
-                                    || unsafe { z_blocks.get_unchecked(_z) }.is_none()
-                                {
-                                    face |= BlockFace::FRONT;
-                                }
-                            }
-                            {
-                                if z == 0 || unsafe { z_blocks.get_unchecked(z - 1) }.is_none() {
-                                    face |= BlockFace::BACK;
-                                }
-                            }
-                        }
+                        continue;
                     }
 
-                    let y_blocks = unsafe { self.blocks.get_unchecked_mut(x) };
-                    let z_blocks = unsafe { y_blocks.get_unchecked_mut(y) };
-                    let maybe_block = unsafe { z_blocks.get_unchecked_mut(z) };
+                    let Some(block) = self.get_lod_block(registry, scale, x, y_offset + local_y, z)
+                    else {
+                        continue;
+                    };
+                    let face = self.get_face(neighbours, registry, scale, x, y_offset + local_y, z);
+                    let is_translucent = registry.get_layer(block.id) == RenderLayer::Translucent;
+
+                    let model = registry.get_model(block.id);
+                    // Fluids are cut at their surface, unless more of the fluid is above
+                    let surface_model;
+                    let model = match registry.get_fluid(block.id) {
+                        Some(fluid)
+                            if scale == 1
+                                && !self.is_same_block_above(&block, x, y_offset + local_y, z) =>
+                        {
+                            surface_model = BlockModel::with_height(
+                                fluid.get_surface_height(block.state.get_level()),
+                            );
+                            &surface_model
+                        }
+                        _ => model,
+                    };
+                    // Other shapes are only kept at full resolution, where cells are blocks
+                    if !model.is_cube() {
+                        let mesh = if is_translucent {
+                            &mut translucent_mesh
+                        } else {
+                            &mut mesh
+                        };
+                        model.mesh(
+                            Vector3::new(x, y_offset + local_y, z),
+                            &block.state.get_rotation(),
+                            &face,
+                            |face| registry.get_texture_pos(block.id, face),
+                            &mut mesh.model_faces,
+                        );
+                        continue;
+                    }
 
-                    if !face.is_empty() {
-                        unsafe { maybe_block.as_mut().unwrap_unchecked() }.face = face;
+                    if face.is_empty() {
+                        continue;
                     }
+                    let faces = if is_translucent {
+                        has_translucent = true;
+                        &mut translucent_faces
+                    } else {
+                        &mut faces
+                    };
+                    faces[get_cell_index(cells, x, local_y, z)] = Some((block, face));
                 }
             }
         }
+
+        mesh.faces = greedy_mesh(&faces, section_y * ChunkSection::SIZE, scale, registry);
+        if has_translucent {
+            translucent_mesh.faces = greedy_mesh(
+                &translucent_faces,
+                section_y * ChunkSection::SIZE,
+                scale,
+                registry,
+            );
+        }
+        (mesh, translucent_mesh)
     }
 
-    pub fn calculate_raw_face_instances(&mut self) {
-        self.raw_face_instances.clear();
-
-        for x in 0..Self::CHUNK_SIDE_BLOCK {
-            for y in 0..Self::CHUNK_VERTICAL_BLOCK {
-                for z in 0..Self::CHUNK_SIDE_BLOCK {
-                    let y_blocks = unsafe { self.blocks.get_unchecked(x) };
-                    let z_blocks = unsafe { y_blocks.get_unchecked(y) };
-                    let maybe_block = unsafe { z_blocks.get_unchecked(z) };
-
-                    if let Some(block) = maybe_block {
-                        let yy = -(Self::CHUNK_VERTICAL_BLOCK as f32 * Block::SIZE / 2.0)
-                            + Block::HALF_SIZE
-                            + (Block::SIZE * y as f32);
-                        let xx = -(Self::CHUNK_SIDE_BLOCK as f32 * Block::SIZE / 2.0)
-                            + Block::HALF_SIZE
-                            + (Block::SIZE * x as f32);
-                        let zz = -(Self::CHUNK_SIDE_BLOCK as f32 * Block::SIZE / 2.0)
-                            + Block::HALF_SIZE
-                            + (Block::SIZE * z as f32);
-
-                        if block.face.contains(BlockFace::RIGHT) {
-                            self.raw_face_instances.push(RawFaceInstance::from(
-                                block.r#type,
-                                BlockFace::RIGHT,
-                                &Transform {
-                                    translation: Translation3::from(
-                                        Vector3::new(xx + Block::HALF_SIZE, yy, zz)
-                                            + self.world_coord,
-                                    ),
-                                    rotation: Rotation3::new(Vector3::new(
-                                        0.0,
-                                        std::f32::consts::FRAC_PI_2,
-                                        0.0,
-                                    )),
-                                },
-                            ));
-                        }
-                        if block.face.contains(BlockFace::LEFT) {
-                            self.raw_face_instances.push(RawFaceInstance::from(
-                                block.r#type,
-                                BlockFace::LEFT,
-                                &Transform {
-                                    translation: Translation3::from(
-                                        Vector3::new(xx - Block::HALF_SIZE, yy, zz)
-                                            + self.world_coord,
-                                    ),
-                                    rotation: Rotation3::new(Vector3::new(
-                                        0.0,
-                                        -std::f32::consts::FRAC_PI_2,
-                                        0.0,
-                                    )),
-                                },
-                            ));
-                        }
-                        if block.face.contains(BlockFace::TOP) {
-                            self.raw_face_instances.push(RawFaceInstance::from(
-                                block.r#type,
-                                BlockFace::TOP,
-                                &Transform {
-                                    translation: Translation3::from(
-                                        Vector3::new(xx, yy + Block::HALF_SIZE, zz)
-                                            + self.world_coord,
-                                    ),
-                                    rotation: Rotation3::new(Vector3::new(
-                                        -std::f32::consts::FRAC_PI_2,
-                                        0.0,
-                                        0.0,
-                                    )),
-                                },
-                            ));
-                        }
-                        if block.face.contains(BlockFace::BOTTOM) {
-                            self.raw_face_instances.push(RawFaceInstance::from(
-                                block.r#type,
-                                BlockFace::BOTTOM,
-                                &Transform {
-                                    translation: Translation3::from(
-                                        Vector3::new(xx, yy - Block::HALF_SIZE, zz)
-                                            + self.world_coord,
-                                    ),
-                                    rotation: Rotation3::new(Vector3::new(
-                                        std::f32::consts::FRAC_PI_2,
-                                        0.0,
-                                        0.0,
-                                    )),
-                                },
-                            ));
-                        }
-                        if block.face.contains(BlockFace::FRONT) {
-                            self.raw_face_instances.push(RawFaceInstance::from(
-                                block.r#type,
-                                BlockFace::FRONT,
-                                &Transform {
-                                    translation: Translation3::from(
-                                        Vector3::new(xx, yy, zz + Block::HALF_SIZE)
-                                            + self.world_coord,
-                                    ),
-                                    rotation: Rotation3::new(Vector3::from_element(0.0)),
-                                },
-                            ));
-                        }
-                        if block.face.contains(BlockFace::BACK) {
-                            self.raw_face_instances.push(RawFaceInstance::from(
-                                block.r#type,
-                                BlockFace::BACK,
-                                &Transform {
-                                    translation: Translation3::from(
-                                        Vector3::new(xx, yy, zz - Block::HALF_SIZE)
-                                            + self.world_coord,
-                                    ),
-                                    rotation: Rotation3::new(Vector3::new(
-                                        0.0,
-                                        std::f32::consts::PI,
-                                        0.0,
-                                    )),
-                                },
-                            ));
-                        }
-                    }
+    // World position of the lowest corner of the chunk, the faces are positioned from it
+    pub fn get_origin(&self) -> Vector3<f32> {
+        self.chunk_coord.get_origin().get_world_coord().coords
+    }
+
+    // Faces and quads of every layer
+    pub fn get_raw_face_instances_len(&self) -> usize {
+        self.sections
+            .iter()
+            .map(|section| section.get_mesh().len() + section.get_translucent_mesh().len())
+            .sum()
+    }
+
+    // Faces of the opaque and cutout cubes
+    pub fn get_raw_face_instances(&self) -> impl Iterator<Item = &RawFaceInstance> {
+        self.sections
+            .iter()
+            .flat_map(|section| section.get_mesh().faces.iter())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Chunk, ChunkNeighbours};
+    use crate::boxworld::block::{Block, BlockFace};
+    use crate::boxworld::position::{ChunkPos, LocalPos};
+    use crate::boxworld::registry::BlockRegistry;
+    use nalgebra::{Vector2, Vector3};
+
+    fn block(registry: &BlockRegistry, name: &str) -> Option<Block> {
+        Some(Block::new(registry.get_id(name).unwrap()))
+    }
+
+    #[test]
+    fn edit_only_dirties_touched_sections() {
+        let registry = BlockRegistry::new();
+        let dirt = block(&registry, "dirt");
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0));
+        chunk.update_mesh(&ChunkNeighbours::default(), &registry);
+        assert!(!chunk.is_dirty());
+
+        chunk.set_block(LocalPos::new(3, 40, 3), dirt);
+        let dirty = chunk
+            .get_sections()
+            .iter()
+            .map(|section| section.is_dirty())
+            .collect::<Vec<bool>>();
+        assert_eq!(
+            dirty,
+            [false, false, true, false, false, false, false, false]
+        );
+
+        // The block on the bottom layer of a section also changes the faces below it
+        chunk.update_mesh(&ChunkNeighbours::default(), &registry);
+        chunk.set_block(LocalPos::new(3, 48, 3), dirt);
+        assert!(chunk.get_sections()[2].is_dirty());
+        assert!(chunk.get_sections()[3].is_dirty());
+    }
+
+    #[test]
+    fn solid_sections_only_mesh_their_shell() {
+        let registry = BlockRegistry::new();
+        let dirt = block(&registry, "dirt");
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0));
+        for x in 0..Chunk::CHUNK_SIDE_BLOCK {
+            for y in 0..32 {
+                for z in 0..Chunk::CHUNK_SIDE_BLOCK {
+                    chunk.set_block(LocalPos::new(x, y, z), dirt);
                 }
             }
         }
+        chunk.update_mesh(&ChunkNeighbours::default(), &registry);
+
+        // A quad for each of the 4 walls of both sections, plus the top and the bottom
+        assert_eq!(chunk.get_raw_face_instances_len(), 4 * 2 + 2);
+        assert!(chunk.get_sections()[2..]
+            .iter()
+            .all(|section| section.get_mesh().is_empty()));
+    }
+
+    #[test]
+    fn faces_behind_non_opaque_blocks_are_kept() {
+        let registry = BlockRegistry::new();
+        let glass = block(&registry, "glass");
+
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0));
+        chunk.set_block(LocalPos::new(3, 40, 3), block(&registry, "dirt"));
+        chunk.set_block(LocalPos::new(4, 40, 3), glass);
+        chunk.set_block(LocalPos::new(5, 40, 3), glass);
+        chunk.set_block(LocalPos::new(6, 40, 3), block(&registry, "water"));
+        chunk.update_mesh(&ChunkNeighbours::default(), &registry);
+
+        let section = &chunk.get_sections()[2];
+        let get_faces = |texture_pos: Vector2<i32>| {
+            section
+                .get_mesh()
+                .faces
+                .iter()
+                .filter(|instance| instance.get_texture_pos() == texture_pos)
+                .map(|instance| instance.get_face())
+                .collect::<Vec<_>>()
+        };
+        // The dirt shows through the glass, the panes hide each other and the glass against
+        // the dirt is hidden
+        assert_eq!(get_faces(Vector2::new(2, 0)).len(), 6);
+        let glass_faces = get_faces(Vector2::new(1, 1));
+        assert_eq!(glass_faces.len(), 5);
+        assert!(!glass_faces.contains(&BlockFace::LEFT));
+
+        // The water is drawn apart, with its face against the glass
+        assert_eq!(section.get_mesh().faces.len(), 11);
+        assert_eq!(section.get_translucent_mesh().model_faces.len(), 6);
+    }
+
+    #[test]
+    fn fluid_is_cut_at_its_surface() {
+        let registry = BlockRegistry::new();
+        let water = Block::new(registry.get_id("water").unwrap());
+        let flowing = Block::with_state(water.id, water.state.with_level(4));
+
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0));
+        chunk.set_block(LocalPos::new(3, 40, 3), Some(water));
+        chunk.set_block(LocalPos::new(3, 41, 3), Some(water));
+        chunk.set_block(LocalPos::new(8, 40, 3), Some(flowing));
+        chunk.update_mesh(&ChunkNeighbours::default(), &registry);
+
+        // Full under more water, without a face between the two
+        let mesh = chunk.get_sections()[2].get_translucent_mesh();
+        assert_eq!(mesh.faces.len(), 5);
+        let surface_heights = mesh
+            .model_faces
+            .iter()
+            .map(|instance| instance.get_origin().y)
+            .filter(|y| *y > 0)
+            .collect::<Vec<u8>>();
+        assert_eq!(mesh.model_faces.len(), 5 + 6);
+        assert_eq!(surface_heights, [8, 14]);
     }
 
-    pub fn get_raw_face_instances(&self) -> &Vec<RawFaceInstance> {
-        &self.raw_face_instances
+    #[test]
+    fn only_full_sides_of_models_cover_faces() {
+        let registry = BlockRegistry::new();
+        let slab = block(&registry, "stone_slab");
+
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0));
+        chunk.set_block(LocalPos::new(3, 40, 3), block(&registry, "dirt"));
+        chunk.set_block(LocalPos::new(3, 41, 3), slab);
+        chunk.set_block(LocalPos::new(4, 41, 3), slab);
+        chunk.update_mesh(&ChunkNeighbours::default(), &registry);
+
+        // The bottom of the slab covers the dirt, the half sides of the slabs do not hide each
+        // other, and the slab against the dirt keeps its bottom hidden
+        let mesh = chunk.get_sections()[2].get_mesh();
+        assert_eq!(mesh.faces.len(), 5);
+        assert_eq!(mesh.model_faces.len(), 5 + 6);
+    }
+
+    #[test]
+    fn distant_chunks_are_meshed_with_bigger_cells() {
+        let registry = BlockRegistry::new();
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0));
+        chunk.set_block(LocalPos::new(3, 40, 3), block(&registry, "dirt"));
+        chunk.update_mesh(&ChunkNeighbours::default(), &registry);
+
+        chunk.set_lod(2);
+        assert!(chunk.is_dirty());
+        chunk.update_mesh(&ChunkNeighbours::default(), &registry);
+
+        // The block fills the 2×2×2 cell it is in
+        let raw_face_instances = chunk.get_raw_face_instances().collect::<Vec<_>>();
+        assert_eq!(raw_face_instances.len(), 6);
+        assert!(raw_face_instances
+            .iter()
+            .all(|instance| instance.get_size() == Vector2::new(2, 2)));
+        let get_position = |face: BlockFace| {
+            raw_face_instances
+                .iter()
+                .find(|instance| instance.get_face() == face)
+                .unwrap()
+                .get_position()
+        };
+        assert_eq!(get_position(BlockFace::BOTTOM), Vector3::new(2, 40, 2));
+        assert_eq!(get_position(BlockFace::TOP), Vector3::new(2, 41, 2));
+        assert_eq!(get_position(BlockFace::RIGHT), Vector3::new(3, 40, 2));
     }
 }
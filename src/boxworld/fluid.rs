@@ -0,0 +1,322 @@
+use crate::boxworld::block::Block;
+use crate::boxworld::model::MODEL_SIZE;
+use crate::boxworld::position::BlockPos;
+use crate::boxworld::registry::{BlockId, BlockRegistry};
+use crate::boxworld::tick::TickContext;
+use crate::boxworld::BoxWorld;
+use serde::Deserialize;
+
+// Level of a block placed as a fluid, that never dries up
+pub const SOURCE_LEVEL: u8 = 0;
+// Level of fluid poured from the fluid above it, filling the block without being a source. The
+// levels in between are the blocks the fluid flowed sideways from the closest source or fall.
+pub const FALLING_LEVEL: u8 = 8;
+pub const MAX_FLOW_DISTANCE: u8 = FALLING_LEVEL - 1;
+
+// Surface of a source, a bit under the top of the block
+const SOURCE_HEIGHT: u8 = MODEL_SIZE - 2;
+// Surface of the blocks furthest from the source
+const MIN_HEIGHT: u8 = 2;
+
+// Left, right, back and front
+const HORIZONTAL_OFFSETS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+// How a block flows and what it does to the bodies in it
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FluidDefinition {
+    // Blocks it flows sideways from a source or a fall, at most MAX_FLOW_DISTANCE
+    pub flow_distance: u8,
+    // Ticks between two steps of the flow
+    pub flow_delay: u64,
+    // Part of the gravity it cancels on a body in it, more than 1 floats
+    pub buoyancy: f32,
+    // Linear damping of a body in it
+    pub drag: f32,
+}
+
+impl FluidDefinition {
+    pub fn get_flow_distance(&self) -> u8 {
+        self.flow_distance.min(MAX_FLOW_DISTANCE)
+    }
+
+    // Height in sixteenths of a block the surface is drawn at, lower the further the fluid flowed
+    pub fn get_surface_height(&self, level: u8) -> u8 {
+        if level >= FALLING_LEVEL {
+            return MODEL_SIZE;
+        }
+        if level == SOURCE_LEVEL {
+            return SOURCE_HEIGHT;
+        }
+        let steps = self.get_flow_distance() + 1;
+        let left = steps.saturating_sub(level);
+        MIN_HEIGHT + (SOURCE_HEIGHT - MIN_HEIGHT) * left / steps
+    }
+}
+
+// Level of the fluid flowing out of a block of the level
+fn get_next_level(level: u8) -> u8 {
+    if level == SOURCE_LEVEL || level == FALLING_LEVEL {
+        1
+    } else {
+        level + 1
+    }
+}
+
+fn with_level(block: Block, level: u8) -> Block {
+    Block::with_state(block.id, block.state.with_level(level))
+}
+
+// Air, or a block the fluid washes away like a plant
+fn can_flow_into(registry: &BlockRegistry, block: Option<Block>) -> bool {
+    block.is_none_or(|block| {
+        registry.get(block.id).is_some_and(|definition| {
+            !definition.solid && !definition.collidable && definition.fluid.is_none()
+        })
+    })
+}
+
+// Level the neighbours give the block, none when nothing feeds it anymore
+fn get_fed_level(
+    world: &BoxWorld,
+    registry: &BlockRegistry,
+    pos: BlockPos,
+    id: BlockId,
+    flow_distance: u8,
+) -> Option<u8> {
+    let get_level = |pos: BlockPos| {
+        world
+            .get_block(pos)
+            .filter(|block| block.id == id)
+            .map(|block| block.state.get_level())
+    };
+    if get_level(pos.offset(0, 1, 0)).is_some() {
+        return Some(FALLING_LEVEL);
+    }
+
+    let levels = HORIZONTAL_OFFSETS.map(|(x, z)| get_level(pos.offset(x, 0, z)));
+    // Two sources make a new one between them, over ground or another source
+    let sources = levels
+        .iter()
+        .filter(|level| **level == Some(SOURCE_LEVEL))
+        .count();
+    let is_held = world.get_block(pos.offset(0, -1, 0)).is_some_and(|below| {
+        (below.id == id && below.state.get_level() == SOURCE_LEVEL)
+            || registry
+                .get(below.id)
+                .is_some_and(|definition| definition.solid)
+    });
+    if sources >= 2 && is_held {
+        return Some(SOURCE_LEVEL);
+    }
+
+    levels
+        .into_iter()
+        .flatten()
+        .map(get_next_level)
+        .min()
+        .filter(|level| *level <= flow_distance)
+}
+
+// Scheduled tick of a fluid block: takes the level its neighbours give it, then pours into the
+// block below, or sideways when it cannot. Every block it changes gets its own tick later on.
+pub fn flow(context: &mut TickContext, pos: BlockPos, block: Block) {
+    let (world, registry) = (&mut *context.world, context.registry);
+    let Some(fluid) = registry.get_fluid(block.id) else {
+        return;
+    };
+    let flow_distance = fluid.get_flow_distance();
+
+    let mut level = block.state.get_level();
+    if level != SOURCE_LEVEL {
+        match get_fed_level(world, registry, pos, block.id, flow_distance) {
+            None => {
+                world.remove_block(pos);
+                return;
+            }
+            Some(fed_level) if fed_level != level => {
+                level = fed_level;
+                world.replace_block(pos, Some(with_level(block, level)));
+            }
+            Some(_) => {}
+        }
+    }
+
+    let below = pos.offset(0, -1, 0);
+    let below_block = world.get_block(below);
+    if can_flow_into(registry, below_block)
+        && world.replace_block(below, Some(with_level(block, FALLING_LEVEL)))
+    {
+        return;
+    }
+    // Already pouring down
+    if below_block.is_some_and(|below| below.id == block.id) {
+        return;
+    }
+
+    let next_level = get_next_level(level);
+    if next_level > flow_distance {
+        return;
+    }
+    for (x, z) in HORIZONTAL_OFFSETS {
+        let side = pos.offset(x, 0, z);
+        let side_block = world.get_block(side);
+        let is_weaker = side_block.is_some_and(|side| {
+            let side_level = side.state.get_level();
+            side.id == block.id
+                && side_level != SOURCE_LEVEL
+                && side_level != FALLING_LEVEL
+                && side_level > next_level
+        });
+        if is_weaker || can_flow_into(registry, side_block) {
+            world.replace_block(side, Some(with_level(block, next_level)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FluidDefinition, FALLING_LEVEL, SOURCE_LEVEL};
+    use crate::boxworld::block::Block;
+    use crate::boxworld::position::BlockPos;
+    use crate::boxworld::registry::{BlockId, BlockRegistry};
+    use crate::boxworld::tick::test::world_with_chunk;
+    use crate::boxworld::tick::BlockTicks;
+    use crate::boxworld::BoxWorld;
+
+    struct Pool {
+        world: BoxWorld,
+        ticks: BlockTicks,
+        registry: BlockRegistry,
+    }
+
+    impl Pool {
+        // A stone floor at y = 0 across the chunk
+        fn new() -> Self {
+            let registry = BlockRegistry::new();
+            let (mut world, ticks) = world_with_chunk(&registry, 0);
+            let stone = registry.get_id("stone").unwrap();
+            for x in 0..16 {
+                for z in 0..16 {
+                    world.set_block(BlockPos::new(x, 0, z), stone);
+                }
+            }
+            Self {
+                world,
+                ticks,
+                registry,
+            }
+        }
+
+        fn get_id(&self, name: &str) -> BlockId {
+            self.registry.get_id(name).unwrap()
+        }
+
+        fn run(&mut self, ticks: usize) {
+            for _ in 0..ticks {
+                self.ticks.run(&mut self.world, &self.registry);
+            }
+        }
+
+        fn get_level(&self, x: i32, y: i32, z: i32) -> Option<u8> {
+            self.world
+                .get_block(BlockPos::new(x, y, z))
+                .filter(|block| self.registry.get_fluid(block.id).is_some())
+                .map(|block| block.state.get_level())
+        }
+    }
+
+    #[test]
+    fn fluid_falls_then_spreads_up_to_its_distance() {
+        let mut pool = Pool::new();
+        let water = pool.get_id("water");
+        let lava = pool.get_id("lava");
+        pool.world.set_block(BlockPos::new(2, 3, 8), water);
+        pool.run(200);
+
+        assert_eq!(pool.get_level(2, 3, 8), Some(SOURCE_LEVEL));
+        // Straight down first, without spreading on the way
+        assert_eq!(pool.get_level(2, 2, 8), Some(FALLING_LEVEL));
+        assert_eq!(pool.get_level(3, 3, 8), None);
+        assert_eq!(pool.get_level(2, 1, 8), Some(FALLING_LEVEL));
+        // Then sideways along the floor
+        assert_eq!(pool.get_level(3, 1, 8), Some(1));
+        assert_eq!(pool.get_level(2, 1, 11), Some(3));
+        assert_eq!(pool.get_level(9, 1, 8), Some(7));
+        assert_eq!(pool.get_level(10, 1, 8), None);
+        // Around the corner, counting the way around
+        assert_eq!(pool.get_level(3, 1, 9), Some(2));
+
+        // Lava does not flow as far
+        pool.world.set_block(BlockPos::new(13, 1, 1), lava);
+        pool.run(400);
+        assert_eq!(pool.get_level(13, 1, 4), Some(3));
+        assert_eq!(pool.get_level(13, 1, 5), None);
+    }
+
+    #[test]
+    fn fluid_dries_up_without_its_source() {
+        let mut pool = Pool::new();
+        let water = pool.get_id("water");
+        pool.world.set_block(BlockPos::new(4, 1, 4), water);
+        pool.run(100);
+        assert_eq!(pool.get_level(6, 1, 4), Some(2));
+
+        pool.world.remove_block(BlockPos::new(4, 1, 4));
+        pool.run(200);
+        for x in 0..16 {
+            for z in 0..16 {
+                assert_eq!(pool.get_level(x, 1, z), None);
+            }
+        }
+    }
+
+    #[test]
+    fn sources_meeting_make_a_new_source() {
+        let mut pool = Pool::new();
+        let water = pool.get_id("water");
+        pool.world.set_block(BlockPos::new(4, 1, 4), water);
+        pool.world.set_block(BlockPos::new(6, 1, 4), water);
+        pool.run(100);
+        assert_eq!(pool.get_level(5, 1, 4), Some(SOURCE_LEVEL));
+
+        // It stays once the others are gone
+        pool.world.remove_block(BlockPos::new(4, 1, 4));
+        pool.world.remove_block(BlockPos::new(6, 1, 4));
+        pool.run(100);
+        assert_eq!(pool.get_level(5, 1, 4), Some(SOURCE_LEVEL));
+        assert_eq!(pool.get_level(6, 1, 4), Some(1));
+    }
+
+    #[test]
+    fn fluid_washes_plants_away_but_not_walls() {
+        let mut pool = Pool::new();
+        let rose = pool.get_id("rose");
+        let stone = pool.get_id("stone");
+        let water = pool.get_id("water");
+        pool.world.set_block(BlockPos::new(5, 1, 5), rose);
+        pool.world.set_block(BlockPos::new(4, 1, 6), stone);
+        pool.world.set_block(BlockPos::new(4, 1, 5), water);
+        pool.run(50);
+
+        assert_eq!(pool.get_level(5, 1, 5), Some(1));
+        assert_eq!(
+            pool.world.get_block(BlockPos::new(4, 1, 6)),
+            Some(Block::new(stone))
+        );
+    }
+
+    #[test]
+    fn surface_is_lower_further_from_the_source() {
+        let water = FluidDefinition {
+            flow_distance: 7,
+            flow_delay: 5,
+            buoyancy: 1.0,
+            drag: 1.0,
+        };
+        let heights = (0..=FALLING_LEVEL)
+            .map(|level| water.get_surface_height(level))
+            .collect::<Vec<u8>>();
+        assert_eq!(heights, [14, 12, 11, 9, 8, 6, 5, 3, 16]);
+    }
+}
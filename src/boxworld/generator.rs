@@ -1,16 +1,123 @@
+use crate::boxworld::block::Block;
+use crate::boxworld::chunk::Chunk;
+use crate::boxworld::position::{ChunkPos, LocalPos};
+use crate::boxworld::registry::{BlockId, BlockRegistry, RegistryError};
 
+#[derive(Clone, Copy)]
+pub struct WorldGenerator {
+    seed: u64,
+    ground: BlockId,
+}
 
+impl WorldGenerator {
+    pub const BASE_HEIGHT: usize = 56;
+    pub const HEIGHT_VARIATION: f32 = 12.0;
+    // Distance in blocks between two noise lattice points
+    pub const NOISE_SCALE: f32 = 24.0;
+    // Name in the registry of the block the terrain is made of
+    pub const GROUND_BLOCK: &'static str = "grass";
 
+    pub fn new(seed: u64, registry: &BlockRegistry) -> Result<Self, RegistryError> {
+        let ground = registry
+            .get_id(Self::GROUND_BLOCK)
+            .ok_or_else(|| RegistryError::MissingBlock(Self::GROUND_BLOCK.to_string()))?;
+        Ok(Self { seed, ground })
+    }
 
-struct WorldGenerator;
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
 
-impl WorldGenerator {
-    pub fn new() -> Self {
-        Self
+    pub fn generate(&self, chunk_coord: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(chunk_coord);
+
+        let origin = chunk_coord.get_origin();
+        for x in 0..Chunk::CHUNK_SIDE_BLOCK {
+            for z in 0..Chunk::CHUNK_SIDE_BLOCK {
+                let height = self.get_height(origin.x + x as i32, origin.z + z as i32);
+                for y in 0..height {
+                    chunk.set_block(LocalPos::new(x, y, z), Some(Block::new(self.ground)));
+                }
+            }
+        }
+
+        chunk
     }
 
-    // pub fn gen_with_center_point(&self, chunk_coord: Vector2<i32>) -> Chunk {
-    //     let mut visible_chunks = VecDeque::with_capacity(World::RENDER_CHUNK * 2 + 1);
-    //     Chunk::from(Some(Block::new(BlockType::Dirt)));
-    // }
+    // Number of solid blocks in the column, from the bottom of the chunk
+    pub fn get_height(&self, x: i32, z: i32) -> usize {
+        let noise = self.get_noise(x as f32 / Self::NOISE_SCALE, z as f32 / Self::NOISE_SCALE)
+            * 0.75
+            + self.get_noise(
+                x as f32 / Self::NOISE_SCALE * 2.0,
+                z as f32 / Self::NOISE_SCALE * 2.0,
+            ) * 0.25;
+        let height = Self::BASE_HEIGHT as f32 + (noise * 2.0 - 1.0) * Self::HEIGHT_VARIATION;
+        (height as usize).clamp(1, Chunk::CHUNK_VERTICAL_BLOCK)
+    }
+
+    // Value noise in [0, 1], smoothly interpolated between the lattice points
+    fn get_noise(&self, x: f32, z: f32) -> f32 {
+        let (x0, z0) = (x.floor() as i32, z.floor() as i32);
+        let (tx, tz) = (smoothstep(x - x0 as f32), smoothstep(z - z0 as f32));
+
+        let top = lerp(self.hash(x0, z0), self.hash(x0 + 1, z0), tx);
+        let bottom = lerp(self.hash(x0, z0 + 1), self.hash(x0 + 1, z0 + 1), tx);
+        lerp(top, bottom, tz)
+    }
+
+    // SplitMix64 of the seed and the lattice point
+    fn hash(&self, x: i32, z: i32) -> f32 {
+        let mut h = self.seed
+            ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (z as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^= h >> 31;
+        (h >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod test {
+    use super::WorldGenerator;
+    use crate::boxworld::registry::{BlockRegistry, RegistryError};
+
+    fn heights(generator: &WorldGenerator) -> Vec<usize> {
+        (-64..64)
+            .flat_map(|x| (-64..64).map(move |z| (x, z)))
+            .map(|(x, z)| generator.get_height(x, z))
+            .collect()
+    }
+
+    fn with_seed(seed: u64) -> WorldGenerator {
+        WorldGenerator::new(seed, &BlockRegistry::new()).unwrap()
+    }
+
+    #[test]
+    fn same_seed_generates_same_terrain() {
+        assert_eq!(heights(&with_seed(42)), heights(&with_seed(42)));
+        assert_ne!(heights(&with_seed(42)), heights(&with_seed(43)));
+    }
+
+    #[test]
+    fn ground_block_has_to_be_registered() {
+        let registry = BlockRegistry::parse(
+            "[(id: 0, name: \"marble\", textures: (side: (0, 0)))]",
+            "{\"cube\": (elements: [Box(from: (0, 0, 0), to: (16, 16, 16))])}",
+        )
+        .unwrap();
+        assert_eq!(
+            WorldGenerator::new(0, &registry).err(),
+            Some(RegistryError::MissingBlock("grass".to_string()))
+        );
+    }
 }
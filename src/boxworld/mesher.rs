@@ -0,0 +1,266 @@
+use crate::boxworld::block::{Block, BlockFace, RawFaceInstance, RawModelFaceInstance};
+use crate::boxworld::registry::{BlockRegistry, FaceTexture};
+use crate::boxworld::section::ChunkSection;
+use nalgebra::{Vector2, Vector3};
+
+const SIZE: usize = ChunkSection::SIZE;
+
+// Block and visible faces of every cell of a section, indexed with get_cell_index. A cell is a
+// single block, or a cube of blocks when the section is downsampled.
+pub type SectionFaces = Vec<Option<(Block, BlockFace)>>;
+
+// Faces of the blocks of a section drawn in the same pass
+#[derive(Clone, Default, Debug)]
+pub struct SectionMesh {
+    // Faces of the cubes, merged by greedy_mesh
+    pub faces: Vec<RawFaceInstance>,
+    // Quads of the blocks with another model
+    pub model_faces: Vec<RawModelFaceInstance>,
+}
+
+impl SectionMesh {
+    pub fn len(&self) -> usize {
+        self.faces.len() + self.model_faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty() && self.model_faces.is_empty()
+    }
+}
+
+// Same layout as ChunkSection::get_index, for sections of `cells` cells on each side
+#[inline]
+pub fn get_cell_index(cells: usize, x: usize, y: usize, z: usize) -> usize {
+    (y * cells + z) * cells + x
+}
+
+// Axis the quad is pushed along, then the axes its width and height run along, matching the
+// orientation vertex.vert gives to the face
+pub struct FaceAxes {
+    pub face: BlockFace,
+    pub normal: usize,
+    pub u: usize,
+    pub v: usize,
+}
+
+pub const FACE_AXES: [FaceAxes; 6] = [
+    FaceAxes {
+        face: BlockFace::RIGHT,
+        normal: 0,
+        u: 2,
+        v: 1,
+    },
+    FaceAxes {
+        face: BlockFace::LEFT,
+        normal: 0,
+        u: 2,
+        v: 1,
+    },
+    FaceAxes {
+        face: BlockFace::TOP,
+        normal: 1,
+        u: 0,
+        v: 2,
+    },
+    FaceAxes {
+        face: BlockFace::BOTTOM,
+        normal: 1,
+        u: 0,
+        v: 2,
+    },
+    FaceAxes {
+        face: BlockFace::FRONT,
+        normal: 2,
+        u: 0,
+        v: 1,
+    },
+    FaceAxes {
+        face: BlockFace::BACK,
+        normal: 2,
+        u: 0,
+        v: 1,
+    },
+];
+
+// Merges the coplanar faces sharing a texture into as few rectangles as possible, one layer of
+// the section at a time. `y_offset` is the height of the section inside its chunk and `scale` the
+// side of a cell in blocks, the quads are sized in blocks whatever the scale.
+pub fn greedy_mesh(
+    faces: &SectionFaces,
+    y_offset: usize,
+    scale: usize,
+    registry: &BlockRegistry,
+) -> Vec<RawFaceInstance> {
+    let cells = SIZE / scale;
+    debug_assert_eq!(faces.len(), cells * cells * cells);
+    let mut raw_face_instances = Vec::new();
+    let mut mask: [[Option<FaceTexture>; SIZE]; SIZE] = [[None; SIZE]; SIZE];
+
+    for axes in FACE_AXES.iter() {
+        let is_positive = matches!(
+            axes.face,
+            BlockFace::RIGHT | BlockFace::TOP | BlockFace::FRONT
+        );
+
+        for layer in 0..cells {
+            // Texture of every visible face of the layer, by u then v
+            for (u, column) in mask[..cells].iter_mut().enumerate() {
+                for (v, cell) in column[..cells].iter_mut().enumerate() {
+                    let mut position = [0; 3];
+                    position[axes.normal] = layer;
+                    position[axes.u] = u;
+                    position[axes.v] = v;
+                    let index = get_cell_index(cells, position[0], position[1], position[2]);
+
+                    *cell = faces[index].as_ref().and_then(|(block, face)| {
+                        face.contains(axes.face.clone())
+                            .then(|| registry.get_face_texture(block, &axes.face))
+                    });
+                }
+            }
+
+            for v in 0..cells {
+                let mut u = 0;
+                while u < cells {
+                    let Some(texture) = mask[u][v] else {
+                        u += 1;
+                        continue;
+                    };
+
+                    let width = (u..cells)
+                        .take_while(|u| mask[*u][v] == Some(texture))
+                        .count();
+                    let height = (v..cells)
+                        .take_while(|v| (u..u + width).all(|u| mask[u][*v] == Some(texture)))
+                        .count();
+                    for column in mask[u..u + width].iter_mut() {
+                        for cell in column[v..v + height].iter_mut() {
+                            *cell = None;
+                        }
+                    }
+
+                    // The quad of a positive face is drawn past the block it is given, the last
+                    // block of the cell
+                    let mut position = [0; 3];
+                    position[axes.normal] = layer * scale + if is_positive { scale - 1 } else { 0 };
+                    position[axes.u] = u * scale;
+                    position[axes.v] = v * scale;
+
+                    raw_face_instances.push(RawFaceInstance::new(
+                        Vector3::from(position) + Vector3::new(0, y_offset, 0),
+                        axes.face.clone(),
+                        Vector2::new(width * scale, height * scale),
+                        texture.pos,
+                        texture.turns,
+                        RawFaceInstance::MAX_LIGHT,
+                    ));
+
+                    u += width;
+                }
+            }
+        }
+    }
+
+    raw_face_instances
+}
+
+#[cfg(test)]
+mod test {
+    use super::{greedy_mesh, SectionFaces, FACE_AXES};
+    use crate::boxworld::block::{Block, BlockFace};
+    use crate::boxworld::registry::BlockRegistry;
+    use crate::boxworld::section::ChunkSection;
+    use crate::boxworld::state::{Axis, BlockState};
+    use nalgebra::Vector3;
+
+    #[test]
+    fn flat_layer_is_one_quad_per_side() {
+        let registry = BlockRegistry::new();
+        let dirt = registry.get_id("dirt").unwrap();
+        let last = ChunkSection::SIZE - 1;
+        let mut faces: SectionFaces = vec![None; ChunkSection::TOTAL_BLOCKS];
+        for x in 0..ChunkSection::SIZE {
+            for z in 0..ChunkSection::SIZE {
+                let mut face = BlockFace::TOP | BlockFace::BOTTOM;
+                face.set(BlockFace::LEFT, x == 0);
+                face.set(BlockFace::RIGHT, x == last);
+                face.set(BlockFace::BACK, z == 0);
+                face.set(BlockFace::FRONT, z == last);
+                faces[ChunkSection::get_index(x, 0, z)] = Some((Block::new(dirt), face));
+            }
+        }
+
+        assert_eq!(greedy_mesh(&faces, 0, 1, &registry).len(), 6);
+    }
+
+    #[test]
+    fn faces_with_different_textures_are_not_merged() {
+        let registry = BlockRegistry::new();
+        let dirt = registry.get_id("dirt").unwrap();
+        let mut faces: SectionFaces = vec![None; ChunkSection::TOTAL_BLOCKS];
+        for x in 0..4 {
+            faces[ChunkSection::get_index(x, 0, 0)] = Some((Block::new(dirt), BlockFace::TOP));
+        }
+        faces[ChunkSection::get_index(1, 0, 0)] = Some((Block::new(dirt), BlockFace::BOTTOM));
+
+        // The top row is split by the block showing only its bottom face
+        assert_eq!(greedy_mesh(&faces, 0, 1, &registry).len(), 3);
+
+        // Nor are the faces of the same block lying another way
+        let mut faces: SectionFaces = vec![None; ChunkSection::TOTAL_BLOCKS];
+        let log = registry.get_id("log").unwrap();
+        let lying = BlockState::default().with_axis(Axis::X);
+        faces[ChunkSection::get_index(0, 0, 0)] = Some((Block::new(log), BlockFace::FRONT));
+        faces[ChunkSection::get_index(1, 0, 0)] =
+            Some((Block::with_state(log, lying), BlockFace::FRONT));
+        assert_eq!(greedy_mesh(&faces, 0, 1, &registry).len(), 2);
+    }
+
+    #[test]
+    fn merged_quads_cover_the_blocks() {
+        let registry = BlockRegistry::new();
+        let dirt = registry.get_id("dirt").unwrap();
+        // Two blocks side by side along x, in the third section of the chunk
+        let mut faces: SectionFaces = vec![None; ChunkSection::TOTAL_BLOCKS];
+        faces[ChunkSection::get_index(0, 0, 0)] =
+            Some((Block::new(dirt), BlockFace::all() - BlockFace::RIGHT));
+        faces[ChunkSection::get_index(1, 0, 0)] =
+            Some((Block::new(dirt), BlockFace::all() - BlockFace::LEFT));
+
+        let raw_face_instances = greedy_mesh(&faces, 32, 1, &registry);
+        assert_eq!(raw_face_instances.len(), 6);
+
+        let mut corners = raw_face_instances
+            .iter()
+            .flat_map(|instance| {
+                let axes = FACE_AXES
+                    .iter()
+                    .find(|axes| axes.face == instance.get_face())
+                    .unwrap();
+                let is_positive = matches!(
+                    axes.face,
+                    BlockFace::RIGHT | BlockFace::TOP | BlockFace::FRONT
+                );
+                let size = instance.get_size();
+                let mut base = instance.get_position();
+                base[axes.normal] += is_positive as usize;
+
+                [(0, 0), (size.x, 0), (size.x, size.y), (0, size.y)].map(move |(du, dv)| {
+                    let mut corner = base;
+                    corner[axes.u] += du;
+                    corner[axes.v] += dv;
+                    corner
+                })
+            })
+            .collect::<Vec<Vector3<usize>>>();
+        corners.sort_by_key(|c| (c.x, c.y, c.z));
+        corners.dedup();
+
+        // Exactly the 8 corners of the 2×1×1 box, nothing sticking out
+        let mut expected = (0..8)
+            .map(|i| Vector3::new((i & 1) * 2, 32 + ((i >> 1) & 1), (i >> 2) & 1))
+            .collect::<Vec<Vector3<usize>>>();
+        expected.sort_by_key(|c| (c.x, c.y, c.z));
+        assert_eq!(corners, expected);
+    }
+}
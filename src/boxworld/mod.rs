@@ -1,34 +1,85 @@
 pub mod block;
 pub mod chunk;
+pub mod fluid;
 pub mod generator;
+pub mod mesher;
+pub mod model;
+pub mod palette;
 pub mod plugin;
+pub mod position;
+pub mod registry;
+pub mod section;
+pub mod state;
+pub mod storage;
 pub mod systems;
+pub mod tick;
+pub mod visibility;
 pub mod voronoi;
 pub mod worker;
 
 use crate::game::camera::Camera;
+use crate::game::config::Config;
 use bevy_ecs::prelude::*;
-use std::borrow::Borrow;
-use std::collections::{HashMap, HashSet};
 use bevy_tasks::{AsyncComputeTaskPool, TaskPoolBuilder};
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
 
-use crate::boxworld::block::{Block, BlockType, RawFaceInstance};
-use crate::boxworld::chunk::Chunk;
+use crate::boxworld::block::{Block, RawFaceInstance};
+use crate::boxworld::chunk::{Chunk, ChunkNeighbours};
+use crate::boxworld::generator::WorldGenerator;
+use crate::boxworld::mesher::SectionMesh;
+use crate::boxworld::position::{BlockPos, ChunkPos};
+use crate::boxworld::registry::{BlockId, BlockRegistry, RegistryError};
+use crate::boxworld::section::ChunkSection;
+use crate::boxworld::state::BlockState;
+use crate::boxworld::storage::{StorageError, WorldStorage};
+use crate::boxworld::tick::ScheduledTick;
+use crate::boxworld::visibility::{find_visible_sections, SectionPos};
 
 use crate::boxworld::worker::{BoxWorldTask, BoxWorldTaskResult};
 
 use crate::renderer::game_renderer::GameRenderer;
 use crate::renderer::Renderer;
-use nalgebra::{try_convert, Vector2, Vector3};
+use nalgebra::{Point3, Vector2, Vector3};
 use winit::dpi::Pixel;
 
+#[derive(Debug, PartialEq)]
+pub enum WorldError {
+    Storage(StorageError),
+    Registry(RegistryError),
+}
+
 #[derive(Resource)]
 pub struct BoxWorld {
-    visible_chunks: HashMap<Vector2<i32>, Chunk>,
-    current_chunk_coord: Vector2<i32>,
+    visible_chunks: HashMap<ChunkPos, Chunk>,
+    current_chunk_coord: ChunkPos,
+
+    // Chunks to load, the next one last
+    load_queue: Vec<ChunkPos>,
+    // Chunks being generated or read, with the entity of their task
+    loading_chunks: HashMap<ChunkPos, Entity>,
+    // Chunks with sections to remesh
+    dirty_chunks: HashSet<ChunkPos>,
+    // Chunks whose GPU buffers are out of date, and the ones to free
+    remeshed_chunks: HashSet<ChunkPos>,
+    unloaded_chunks: HashSet<ChunkPos>,
+
+    render_distance: usize,
+    // The render distance changed since the chunks to load and unload were last looked for
+    range_changed: bool,
+    lod_distance: usize,
+    worker_threads: usize,
+    max_loading_chunks: usize,
 
-    enqueued_chunk: HashSet<Vector2<i32>>,
-    is_dirty: bool,
+    generator: WorldGenerator,
+    storage: Option<WorldStorage>,
+    // Chunks changed since they were loaded, saved again when they are unloaded
+    modified_chunks: HashSet<ChunkPos>,
+
+    // Game ticks since the world was opened
+    tick: u64,
+    // Blocks set or removed since the last tick, the blocks around them get updated
+    changed_blocks: Vec<BlockPos>,
 }
 
 impl BoxWorld {
@@ -39,129 +90,562 @@ impl BoxWorld {
     pub const FRONT: Vector3<f32> = Vector3::new(0.0, 0.0, 1.0);
     pub const BACK: Vector3<f32> = Vector3::new(0.0, 0.0, -1.0);
 
-    pub const RENDER_CHUNK: usize = 3;
-    pub const TOTAL_CHUNKS: usize = (Self::RENDER_CHUNK * 2 + 1) * (Self::RENDER_CHUNK * 2 + 1);
-    pub const TOTAL_CHUNK_BLOCKS: usize = Chunk::MAXIMUM_TOTAL_BLOCKS * BoxWorld::TOTAL_CHUNKS;
+    pub const RENDER_DISTANCE_RANGE: RangeInclusive<usize> = 1..=32;
+    // Chunks are unloaded this many chunks further than the render distance, so crossing back
+    // and forth over a chunk border does not reload the ones on the edge
+    const UNLOAD_MARGIN: f32 = 1.0;
+
+    // Cell sides the chunks are meshed with, from the closest chunks to the furthest
+    const LOD_SCALES: [usize; 4] = [1, 2, 4, 8];
+    // How many chunks closer than where a level starts the camera has to be to leave it, so
+    // moving back and forth over the limit does not remesh the chunks every time
+    const LOD_HYSTERESIS: f32 = 1.0;
+
+    // Left, right, back and front, as in ChunkNeighbours
+    const NEIGHBOUR_OFFSETS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+    pub fn new(config: &Config, registry: &BlockRegistry) -> Result<Self, WorldError> {
+        let render_distance = Self::clamp_render_distance(config.render_distance);
+        let total_chunks = (render_distance * 2 + 1) * (render_distance * 2 + 1);
+
+        let mut seed = config.seed;
+        let storage = match &config.world_path {
+            Some(path) => {
+                let (storage, level) =
+                    WorldStorage::open(path, seed).map_err(WorldError::Storage)?;
+                if level.seed != seed {
+                    log::info!("Using the seed {} stored in {:?}", level.seed, path);
+                }
+                seed = level.seed;
+                Some(storage)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            visible_chunks: HashMap::with_capacity(total_chunks),
+            current_chunk_coord: ChunkPos::new(i32::MAX, i32::MAX),
+            load_queue: Vec::new(),
+            loading_chunks: HashMap::new(),
+            dirty_chunks: HashSet::new(),
+            remeshed_chunks: HashSet::new(),
+            unloaded_chunks: HashSet::new(),
+            render_distance,
+            range_changed: false,
+            lod_distance: config.lod_distance,
+            worker_threads: config.worker_threads,
+            max_loading_chunks: config.max_loading_chunks.max(1),
+            generator: WorldGenerator::new(seed, registry).map_err(WorldError::Registry)?,
+            storage,
+            modified_chunks: HashSet::new(),
+            tick: 0,
+            changed_blocks: Vec::new(),
+        })
+    }
 
-    pub const WORKER_COUNT: usize = 2;
+    pub fn get_render_distance(&self) -> usize {
+        self.render_distance
+    }
 
-    pub fn new() -> Self {
-        Self {
-            visible_chunks: HashMap::with_capacity(Self::TOTAL_CHUNKS),
-            current_chunk_coord: Vector2::new(i32::MAX, i32::MAX),
-            is_dirty: true,
-            enqueued_chunk: HashSet::new(),
+    fn clamp_render_distance(render_distance: usize) -> usize {
+        render_distance.clamp(
+            *Self::RENDER_DISTANCE_RANGE.start(),
+            *Self::RENDER_DISTANCE_RANGE.end(),
+        )
+    }
+
+    pub fn set_render_distance(&mut self, render_distance: usize) {
+        let render_distance = Self::clamp_render_distance(render_distance);
+        if self.render_distance != render_distance {
+            self.render_distance = render_distance;
+            self.range_changed = true;
         }
     }
 
-    fn enqueue_work(&mut self, mut commands: Commands) {
-        let needed_chunk_coord = self.needed_chunk_coord();
+    // Whether the render distance changed since the last call
+    pub fn take_range_changed(&mut self) -> bool {
+        std::mem::take(&mut self.range_changed)
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.generator.get_seed()
+    }
+
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+
+    // Returns the new tick
+    pub fn advance_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    pub fn get_loaded_chunk_count(&self) -> usize {
+        self.visible_chunks.len()
+    }
+
+    pub fn has_pending_chunks(&self) -> bool {
+        !self.load_queue.is_empty() || !self.loading_chunks.is_empty()
+    }
+
+    pub fn is_loading(&self, chunk_coord: &ChunkPos) -> bool {
+        self.loading_chunks.contains_key(chunk_coord)
+    }
+
+    // Queues the chunks in range that are neither loaded nor loading, replacing the previous
+    // queue. `direction` is where the camera looks on the xz plane.
+    pub fn update_load_queue(&mut self, direction: &Vector2<f32>) {
+        let mut load_queue = self.needed_chunk_coord();
+        load_queue.sort_by(|a, b| {
+            let priority_a = self.get_load_priority(a, direction);
+            let priority_b = self.get_load_priority(b, direction);
+            priority_b.total_cmp(&priority_a)
+        });
+        self.load_queue = load_queue;
+    }
 
-        self.enqueued_chunk.extend(&needed_chunk_coord);
+    // Distance in chunks, counting up to twice for the chunks behind the camera
+    fn get_load_priority(&self, chunk_coord: &ChunkPos, direction: &Vector2<f32>) -> f32 {
+        let offset = (*chunk_coord - self.current_chunk_coord).cast::<f32>();
+        let distance = offset.norm();
+        if distance == 0.0 {
+            return 0.0;
+        }
+        distance * (1.5 - 0.5 * offset.dot(direction) / distance)
+    }
 
+    // Dropping the task of a chunk now out of range cancels it
+    pub fn cancel_far_loads(&mut self, commands: &mut Commands) {
+        let far_chunks = self
+            .loading_chunks
+            .keys()
+            .filter(|chunk_coord| self.is_too_far(chunk_coord))
+            .cloned()
+            .collect::<Vec<ChunkPos>>();
+        for chunk_coord in far_chunks {
+            let entity = self.loading_chunks.remove(&chunk_coord).unwrap();
+            commands.entity(entity).despawn();
+        }
+    }
+
+    // Starts loading the next queued chunks, up to max_loading_chunks at the same time
+    pub fn spawn_loads(&mut self, commands: &mut Commands) {
+        let worker_threads = self.worker_threads;
         let task_pool = AsyncComputeTaskPool::get_or_init(|| {
             TaskPoolBuilder::default()
-                .num_threads(2)
+                .num_threads(worker_threads)
                 .thread_name("Async Compute Task Pool".to_string())
                 .build()
         });
 
-        for chunk_coord in needed_chunk_coord {
+        while self.loading_chunks.len() < self.max_loading_chunks {
+            let Some(chunk_coord) = self.load_queue.pop() else {
+                break;
+            };
+            let generator = self.generator;
+            let storage = self.storage.clone();
+            let tick = self.tick;
             let task = task_pool.spawn(async move {
-                let chunk = Chunk::with_block(Some(Block::new(BlockType::Dirt)), chunk_coord);
+                let chunk = match storage {
+                    Some(storage) => match storage.load_chunk(chunk_coord, tick) {
+                        Ok(Some(chunk)) => chunk,
+                        // Generated the same way the next time, saved once it is modified
+                        Ok(None) => generator.generate(chunk_coord),
+                        Err(err) => {
+                            log::warn!(
+                                "Failed to load chunk {:?}, generating it again: {:?}",
+                                chunk_coord,
+                                err
+                            );
+                            generator.generate(chunk_coord)
+                        }
+                    },
+                    None => generator.generate(chunk_coord),
+                };
                 BoxWorldTaskResult {
                     chunk,
                     coord: chunk_coord,
                 }
             });
-            commands.spawn(BoxWorldTask(task));
+            let entity = commands.spawn(BoxWorldTask(task)).id();
+            self.loading_chunks.insert(chunk_coord, entity);
         }
     }
 
     pub fn update_current_chunk_coord(&mut self, camera: &Camera) -> bool {
-        let current_chunk_coord =
-            Self::get_chunk_coord_from_world_coord(&camera.position.xz().coords);
+        let current_chunk_coord = camera.get_chunk_coord();
         let ret = self.current_chunk_coord != current_chunk_coord;
         self.current_chunk_coord = current_chunk_coord;
         ret
     }
 
-    pub fn insert_chunk(&mut self, coord: Vector2<i32>, chunk: Chunk) {
-        self.enqueued_chunk.remove(&coord);
+    // The chunk is meshed on the next update_meshes, along with the neighbours whose border faces
+    // it now covers
+    pub fn insert_chunk(&mut self, coord: ChunkPos, mut chunk: Chunk) {
+        self.loading_chunks.remove(&coord);
+        chunk.set_lod(Self::get_lod_for_distance(
+            self.lod_distance,
+            self.get_chunk_distance(&coord),
+            None,
+        ));
         self.visible_chunks.insert(coord, chunk);
-        self.is_dirty = true;
+        self.dirty_chunks.insert(coord);
+        self.mark_neighbours_dirty(&coord);
+    }
+
+    fn mark_neighbours_dirty(&mut self, chunk_coord: &ChunkPos) {
+        for (x, z) in Self::NEIGHBOUR_OFFSETS {
+            let neighbour_coord = chunk_coord.offset(x, z);
+            if let Some(neighbour) = self.visible_chunks.get_mut(&neighbour_coord) {
+                neighbour.mark_dirty();
+                self.dirty_chunks.insert(neighbour_coord);
+            }
+        }
+    }
+
+    pub fn get_neighbours(&self, chunk_coord: &ChunkPos) -> ChunkNeighbours<'_> {
+        let [left, right, back, front] = Self::NEIGHBOUR_OFFSETS
+            .map(|(x, z)| self.visible_chunks.get(&chunk_coord.offset(x, z)));
+        ChunkNeighbours {
+            left,
+            right,
+            back,
+            front,
+        }
     }
 
     pub fn is_dirty(&self) -> bool {
-        self.is_dirty
+        !self.remeshed_chunks.is_empty() || !self.unloaded_chunks.is_empty()
     }
 
-    pub fn update_blocks(&mut self, renderer: &Renderer, game_renderer: &mut GameRenderer) {
-        // Remove far blocks
-        let max_diff = Self::RENDER_CHUNK as i32;
-
-        let mut chunk_to_remove = Vec::new();
-        for chunk_coord in self.visible_chunks.keys() {
-            let diff: Vector2<i32> = chunk_coord - self.current_chunk_coord;
-            let diagonal_diff = ((diff.component_mul(&diff)).sum() as f32).sqrt() as i32;
-            if diagonal_diff > max_diff {
-                chunk_to_remove.push(*chunk_coord);
+    pub fn get_chunk(&self, chunk_coord: &ChunkPos) -> Option<&Chunk> {
+        self.visible_chunks.get(chunk_coord)
+    }
+
+    pub fn get_chunks(&self) -> impl Iterator<Item = (&ChunkPos, &Chunk)> {
+        self.visible_chunks.iter()
+    }
+
+    // None for air and for blocks in chunks that are not loaded
+    pub fn get_block(&self, pos: BlockPos) -> Option<Block> {
+        let local = pos.get_local()?;
+        self.visible_chunks
+            .get(&pos.get_chunk_coord())?
+            .get_block(local)
+    }
+
+    // Returns false when the chunk of the block is not loaded
+    pub fn set_block(&mut self, pos: BlockPos, id: BlockId) -> bool {
+        self.replace_block(pos, Some(Block::new(id)))
+    }
+
+    // Sets the block with the state its properties take from the direction the player looks toward
+    pub fn place_block(
+        &mut self,
+        pos: BlockPos,
+        id: BlockId,
+        look_direction: &Vector3<f32>,
+        registry: &BlockRegistry,
+    ) -> bool {
+        let state = BlockState::from_look_direction(registry.get_properties(id), look_direction);
+        self.replace_block(pos, Some(Block::with_state(id, state)))
+    }
+
+    pub fn remove_block(&mut self, pos: BlockPos) -> bool {
+        self.replace_block(pos, None)
+    }
+
+    // Sets the block with its state as it is, none for air
+    pub fn replace_block(&mut self, pos: BlockPos, block: Option<Block>) -> bool {
+        let Some(local) = pos.get_local() else {
+            return false;
+        };
+        let chunk_coord = pos.get_chunk_coord();
+        let Some(chunk) = self.visible_chunks.get_mut(&chunk_coord) else {
+            return false;
+        };
+        chunk.set_block(local, block);
+        self.dirty_chunks.insert(chunk_coord);
+        self.modified_chunks.insert(chunk_coord);
+        self.changed_blocks.push(pos);
+
+        // A block on the chunk border also changes the faces of the block next to it
+        let last = Chunk::CHUNK_SIDE_BLOCK - 1;
+        let is_on_border = [local.x == 0, local.x == last, local.z == 0, local.z == last];
+        for (is_on_border, (x, z)) in is_on_border.into_iter().zip(Self::NEIGHBOUR_OFFSETS) {
+            let neighbour_coord = chunk_coord.offset(x, z);
+            if let (true, Some(neighbour)) =
+                (is_on_border, self.visible_chunks.get_mut(&neighbour_coord))
+            {
+                neighbour.mark_section_dirty(local.y);
+                self.dirty_chunks.insert(neighbour_coord);
             }
         }
-        for chunk_coord in chunk_to_remove.iter() {
-            self.visible_chunks.remove(chunk_coord);
+        true
+    }
+
+    pub fn take_changed_blocks(&mut self) -> Vec<BlockPos> {
+        std::mem::take(&mut self.changed_blocks)
+    }
+
+    // Asks for the block to be updated in `delay` ticks, at least one. Returns false when the
+    // chunk of the block is not loaded.
+    pub fn schedule_tick(&mut self, pos: BlockPos, id: BlockId, delay: u64) -> bool {
+        let Some(local) = pos.get_local() else {
+            return false;
+        };
+        let chunk_coord = pos.get_chunk_coord();
+        let Some(chunk) = self.visible_chunks.get_mut(&chunk_coord) else {
+            return false;
+        };
+        chunk.schedule_tick(ScheduledTick {
+            tick: self.tick + delay.max(1),
+            pos: local,
+            id,
+        });
+        self.modified_chunks.insert(chunk_coord);
+        true
+    }
+
+    // Scheduled ticks due by `tick`, taken out of their chunks, the earliest first
+    pub fn take_due_ticks(&mut self, tick: u64) -> Vec<(BlockPos, BlockId)> {
+        let mut chunk_coords = self
+            .visible_chunks
+            .keys()
+            .cloned()
+            .collect::<Vec<ChunkPos>>();
+        chunk_coords.sort_by_key(|chunk_coord| (chunk_coord.x, chunk_coord.z));
+        let mut due_ticks = Vec::new();
+        for chunk_coord in chunk_coords {
+            let chunk = self.visible_chunks.get_mut(&chunk_coord).unwrap();
+            let chunk_ticks = chunk.take_due_ticks(tick);
+            if !chunk_ticks.is_empty() {
+                self.modified_chunks.insert(chunk_coord);
+            }
+            due_ticks.extend(chunk_ticks.into_iter().map(|scheduled| {
+                (
+                    scheduled.tick,
+                    BlockPos::from_local(&chunk_coord, scheduled.pos),
+                    scheduled.id,
+                )
+            }));
         }
+        due_ticks.sort_by_key(|(tick, _, _)| *tick);
+        due_ticks
+            .into_iter()
+            .map(|(_, pos, id)| (pos, id))
+            .collect()
+    }
 
-        let raw_face_instances = self.get_raw_face_instances();
+    // Remeshes the dirty sections of the edited and newly loaded chunks
+    pub fn update_meshes(&mut self, registry: &BlockRegistry) {
+        for chunk_coord in std::mem::take(&mut self.dirty_chunks) {
+            // Taken out of the map so its neighbours can be borrowed while it is meshed
+            if let Some(mut chunk) = self.visible_chunks.remove(&chunk_coord) {
+                chunk.update_mesh(&self.get_neighbours(&chunk_coord), registry);
+                self.visible_chunks.insert(chunk_coord, chunk);
+                self.remeshed_chunks.insert(chunk_coord);
+            }
+        }
+    }
 
-        game_renderer.update_blocks(
-            &renderer.render_context,
-            &raw_face_instances
-                .cloned()
-                .collect::<Vec<RawFaceInstance>>(),
-            self.get_raw_face_instances_len(),
-        );
+    // Sections the camera can see through air, none when the camera is not in a loaded chunk and
+    // everything has to be drawn. Above or below the world the walk starts from the closest
+    // section.
+    pub fn get_visible_sections(
+        &self,
+        camera_position: &Point3<f32>,
+        is_in_view: impl Fn(&SectionPos) -> bool,
+    ) -> Option<HashSet<SectionPos>> {
+        let camera_block = BlockPos::from_world_coord(camera_position);
+        let chunk_coord = camera_block.get_chunk_coord();
+        self.visible_chunks.get(&chunk_coord)?;
+        let section_y = (camera_block.y - BlockPos::MIN_Y)
+            .div_euclid(ChunkSection::SIZE as i32)
+            .clamp(0, Chunk::SECTION_COUNT as i32 - 1);
 
-        self.is_dirty = false;
+        let get_visibility = |pos: &SectionPos| {
+            let section_y = usize::try_from(pos.y).ok()?;
+            let chunk = self.visible_chunks.get(&ChunkPos::new(pos.x, pos.z))?;
+            Some(*chunk.get_sections().get(section_y)?.get_visibility())
+        };
+        Some(find_visible_sections(
+            Vector3::new(chunk_coord.x, section_y, chunk_coord.z),
+            get_visibility,
+            is_in_view,
+        ))
     }
 
-    fn needed_chunk_coord(&self) -> HashSet<Vector2<i32>> {
-        let corner_relative_coord = Self::RENDER_CHUNK as i32;
+    // Distance in chunks from the chunk of the camera
+    fn get_chunk_distance(&self, chunk_coord: &ChunkPos) -> f32 {
+        chunk_coord.distance(&self.current_chunk_coord)
+    }
 
-        let mut needed_chunk = HashSet::<Vector2<i32>>::new();
-        for x in -corner_relative_coord..=corner_relative_coord {
-            for z in -corner_relative_coord..=corner_relative_coord {
-                let current_chunk_coord = self.current_chunk_coord + Vector2::new(x, z);
-                needed_chunk.insert(current_chunk_coord);
+    // The level of a scale starts at lod_distance * scale / 2 chunks. A coarser level is used as
+    // soon as the chunk is far enough, a finer one only once it is well inside it.
+    pub fn get_lod_for_distance(
+        lod_distance: usize,
+        distance: f32,
+        current: Option<usize>,
+    ) -> usize {
+        let start = |scale: usize| (lod_distance * scale / 2) as f32;
+        if lod_distance == 0 {
+            return 1;
+        }
+
+        let lod = *Self::LOD_SCALES
+            .iter()
+            .rev()
+            .find(|scale| **scale == 1 || distance >= start(**scale))
+            .unwrap();
+        match current {
+            Some(current) if lod < current && distance >= start(current) - Self::LOD_HYSTERESIS => {
+                current
+            }
+            _ => lod,
+        }
+    }
+
+    // Remeshes the chunks whose distance to the camera asks for another resolution
+    pub fn update_lods(&mut self) {
+        let mut changed_chunks = Vec::new();
+        for (chunk_coord, chunk) in self.visible_chunks.iter() {
+            let lod = Self::get_lod_for_distance(
+                self.lod_distance,
+                self.get_chunk_distance(chunk_coord),
+                Some(chunk.get_lod()),
+            );
+            if lod != chunk.get_lod() {
+                changed_chunks.push((*chunk_coord, lod));
             }
         }
+        for (chunk_coord, lod) in changed_chunks {
+            self.visible_chunks
+                .get_mut(&chunk_coord)
+                .unwrap()
+                .set_lod(lod);
+            self.dirty_chunks.insert(chunk_coord);
+        }
+    }
 
-        let visible_coords = self
+    pub fn get_current_chunk_coord(&self) -> &ChunkPos {
+        &self.current_chunk_coord
+    }
+
+    fn is_in_render_distance(&self, chunk_coord: &ChunkPos) -> bool {
+        self.get_chunk_distance(chunk_coord) <= self.render_distance as f32
+    }
+
+    fn is_too_far(&self, chunk_coord: &ChunkPos) -> bool {
+        self.get_chunk_distance(chunk_coord) > self.render_distance as f32 + Self::UNLOAD_MARGIN
+    }
+
+    pub fn remove_far_chunks(&mut self) {
+        let chunk_to_remove = self
             .visible_chunks
             .keys()
+            .filter(|chunk_coord| self.is_too_far(chunk_coord))
+            .cloned()
+            .collect::<Vec<ChunkPos>>();
+        let mut removed_chunks = Vec::with_capacity(chunk_to_remove.len());
+        for chunk_coord in chunk_to_remove {
+            // Kept loaded when it cannot be saved, so its changes are not lost
+            if self.modified_chunks.contains(&chunk_coord) && !self.save_chunk(&chunk_coord) {
+                continue;
+            }
+            self.visible_chunks.remove(&chunk_coord);
+            removed_chunks.push(chunk_coord);
+            self.dirty_chunks.remove(&chunk_coord);
+            self.remeshed_chunks.remove(&chunk_coord);
+            self.unloaded_chunks.insert(chunk_coord);
+        }
+        // Their border faces toward the unloaded chunks are visible again
+        for chunk_coord in removed_chunks.iter() {
+            self.mark_neighbours_dirty(chunk_coord);
+        }
+    }
+
+    // The chunks that fail to be written stay marked as modified
+    pub fn save_modified_chunks(&mut self) {
+        let chunk_coords = self
+            .modified_chunks
+            .iter()
             .cloned()
-            .collect::<HashSet<Vector2<i32>>>();
-        (&needed_chunk - &visible_coords).borrow() - &self.enqueued_chunk
+            .collect::<Vec<ChunkPos>>();
+        for chunk_coord in chunk_coords {
+            self.save_chunk(&chunk_coord);
+        }
     }
 
-    #[inline]
-    fn get_world_center_block_coordinate() -> Vector2<usize> {
-        Vector2::new(Self::RENDER_CHUNK, Self::RENDER_CHUNK)
+    // Writes a loaded chunk and marks it as saved, returns false when it could not be written
+    fn save_chunk(&mut self, chunk_coord: &ChunkPos) -> bool {
+        let (Some(storage), Some(chunk)) =
+            (self.storage.as_ref(), self.visible_chunks.get(chunk_coord))
+        else {
+            self.modified_chunks.remove(chunk_coord);
+            return true;
+        };
+        match storage.save_chunk(chunk, self.tick) {
+            Ok(_) => {
+                self.modified_chunks.remove(chunk_coord);
+                true
+            }
+            Err(err) => {
+                log::warn!("Failed to save chunk {:?}: {:?}", chunk_coord, err);
+                false
+            }
+        }
     }
 
-    pub fn get_chunk_coord_from_world_coord(world_coord: &Vector2<f32>) -> Vector2<i32> {
-        unsafe { try_convert(world_coord / Chunk::CHUNK_SIDE_SIZE).unwrap_unchecked() }
+    // Frees the buffers of the unloaded chunks and uploads the remeshed ones only
+    pub fn update_blocks(&mut self, renderer: &Renderer, game_renderer: &mut GameRenderer) {
+        for chunk_coord in std::mem::take(&mut self.unloaded_chunks) {
+            game_renderer.remove_chunk(&chunk_coord);
+        }
+        for chunk_coord in std::mem::take(&mut self.remeshed_chunks) {
+            if let Some(chunk) = self.visible_chunks.get(&chunk_coord) {
+                game_renderer.update_chunk(
+                    &renderer.render_context,
+                    chunk_coord,
+                    &chunk.get_origin(),
+                    &chunk
+                        .get_sections()
+                        .iter()
+                        .map(|section| section.get_mesh())
+                        .collect::<Vec<&SectionMesh>>(),
+                    &chunk
+                        .get_sections()
+                        .iter()
+                        .map(|section| section.get_translucent_mesh())
+                        .collect::<Vec<&SectionMesh>>(),
+                );
+            }
+        }
     }
 
-    pub fn get_world_coord_from_chunk_coord(world_coord: &Vector2<i32>) -> Vector2<f32> {
-        (world_coord * Chunk::CHUNK_SIDE_SIZE as i32
-            + Vector2::from_element(Chunk::CHUNK_HALF_SIDE_SIZE as i32))
-        .cast::<f32>()
+    // Chunks in range that are neither loaded nor loading
+    fn needed_chunk_coord(&self) -> Vec<ChunkPos> {
+        let corner_relative_coord = self.render_distance as i32;
+
+        let mut needed_chunk = Vec::new();
+        for x in -corner_relative_coord..=corner_relative_coord {
+            for z in -corner_relative_coord..=corner_relative_coord {
+                let chunk_coord = self.current_chunk_coord.offset(x, z);
+                if self.is_in_render_distance(&chunk_coord)
+                    && !self.visible_chunks.contains_key(&chunk_coord)
+                    && !self.loading_chunks.contains_key(&chunk_coord)
+                {
+                    needed_chunk.push(chunk_coord);
+                }
+            }
+        }
+        needed_chunk
     }
 
     pub fn get_raw_face_instances_len(&self) -> u32 {
         self.visible_chunks.values().fold(0usize, |len, chunk| {
-            len + chunk.get_raw_face_instances().len()
+            len + chunk.get_raw_face_instances_len()
         }) as u32
     }
 
@@ -172,7 +656,269 @@ impl BoxWorld {
     }
 }
 
+#[cfg(test)]
 mod test {
+    use super::BoxWorld;
+    use crate::boxworld::chunk::Chunk;
+    use crate::boxworld::position::{BlockPos, ChunkPos};
+    use crate::boxworld::registry::BlockRegistry;
+    use crate::boxworld::state::{Facing, Half};
+    use crate::boxworld::storage::WorldStorage;
+    use crate::game::config::Config;
+    use nalgebra::{Point3, Vector2, Vector3};
+
     #[test]
     fn indices_to_world_coordinate() {}
+
+    fn world_with_chunks(chunk_coords: &[ChunkPos]) -> BoxWorld {
+        let mut world = BoxWorld::new(&Config::default(), &BlockRegistry::new()).unwrap();
+        world.current_chunk_coord = ChunkPos::new(0, 0);
+        for chunk_coord in chunk_coords {
+            world.insert_chunk(*chunk_coord, Chunk::new(*chunk_coord));
+        }
+        world.update_meshes(&BlockRegistry::new());
+        world
+    }
+
+    #[test]
+    fn set_block_is_read_back() {
+        let dirt = BlockRegistry::new().get_id("dirt").unwrap();
+        let mut world = world_with_chunks(&[ChunkPos::new(-1, 0)]);
+        let pos = BlockPos::new(-3, 10, 5);
+
+        assert!(world.get_block(pos).is_none());
+        assert!(world.set_block(pos, dirt));
+        assert_eq!(world.get_block(pos).unwrap().id, dirt);
+        assert!(world.remove_block(pos));
+        assert!(world.get_block(pos).is_none());
+
+        // Not loaded
+        assert!(!world.set_block(BlockPos::new(100, 0, 0), dirt));
+    }
+
+    #[test]
+    fn modified_chunks_are_saved() {
+        let path = std::env::temp_dir().join("boxcraft-modified-chunks-test");
+        let _ = std::fs::remove_dir_all(&path);
+        let config = Config {
+            world_path: Some(path.clone()),
+            ..Config::default()
+        };
+        let registry = BlockRegistry::new();
+        let dirt = registry.get_id("dirt").unwrap();
+        let mut world = BoxWorld::new(&config, &registry).unwrap();
+        let chunk_coord = ChunkPos::new(0, 0);
+        world.insert_chunk(chunk_coord, Chunk::new(chunk_coord));
+        let pos = BlockPos::new(3, 10, 5);
+        assert!(world.set_block(pos, dirt));
+
+        world.save_modified_chunks();
+        assert!(world.modified_chunks.is_empty());
+        let (storage, _) = WorldStorage::open(&path, 0).unwrap();
+        let chunk = storage.load_chunk(chunk_coord, 0).unwrap().unwrap();
+        assert_eq!(chunk.get_block(pos.get_local().unwrap()).unwrap().id, dirt);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn placed_block_faces_away_from_the_player() {
+        let mut world = world_with_chunks(&[ChunkPos::new(0, 0)]);
+        let pos = BlockPos::new(3, 10, 5);
+        let registry = BlockRegistry::new();
+        let stairs = registry.get_id("planks_stairs").unwrap();
+        let dirt = registry.get_id("dirt").unwrap();
+
+        assert!(world.place_block(pos, stairs, &Vector3::new(-0.9, -0.3, 0.2), &registry));
+        let state = world.get_block(pos).unwrap().state;
+        assert_eq!(state.get_facing(), Facing::West);
+        assert_eq!(state.get_half(), Half::Bottom);
+
+        // Blocks without properties keep the default state
+        assert!(world.place_block(pos, dirt, &Vector3::new(0.0, 1.0, 0.0), &registry));
+        assert_eq!(world.get_block(pos).unwrap().state, Default::default());
+    }
+
+    #[test]
+    fn border_edit_remeshes_neighbour_chunk() {
+        let dirt = BlockRegistry::new().get_id("dirt").unwrap();
+        let mut world = world_with_chunks(&[ChunkPos::new(0, 0), ChunkPos::new(1, 0)]);
+        world.set_block(BlockPos::new(15, 5, 3), dirt);
+
+        let section_dirty = |world: &BoxWorld, chunk_coord: ChunkPos| {
+            world
+                .get_chunk(&chunk_coord)
+                .unwrap()
+                .get_sections()
+                .iter()
+                .map(|section| section.is_dirty())
+                .collect::<Vec<bool>>()
+        };
+        let expected = [false, false, false, false, true, false, false, false];
+        assert_eq!(section_dirty(&world, ChunkPos::new(0, 0)), expected);
+        assert_eq!(section_dirty(&world, ChunkPos::new(1, 0)), expected);
+
+        world.update_meshes(&BlockRegistry::new());
+        assert!(!world.get_chunk(&ChunkPos::new(1, 0)).unwrap().is_dirty());
+        assert_eq!(
+            world
+                .get_chunk(&ChunkPos::new(0, 0))
+                .unwrap()
+                .get_raw_face_instances_len(),
+            6
+        );
+    }
+
+    #[test]
+    fn faces_between_loaded_chunks_are_culled() {
+        let dirt = BlockRegistry::new().get_id("dirt").unwrap();
+        let mut world = world_with_chunks(&[ChunkPos::new(0, 0)]);
+        for x in 0..32 {
+            world.set_block(BlockPos::new(x, 0, 0), dirt);
+        }
+        world.update_meshes(&BlockRegistry::new());
+        let face_count = |world: &BoxWorld| {
+            world
+                .get_chunk(&ChunkPos::new(0, 0))
+                .unwrap()
+                .get_raw_face_instances_len()
+        };
+        // 16 blocks in a row, merged into a single quad on every side
+        assert_eq!(face_count(&world), 6);
+
+        world.insert_chunk(ChunkPos::new(1, 0), Chunk::new(ChunkPos::new(1, 0)));
+        for x in 16..32 {
+            world.set_block(BlockPos::new(x, 0, 0), dirt);
+        }
+        world.update_meshes(&BlockRegistry::new());
+        assert_eq!(face_count(&world), 5);
+
+        // Only the chunk on the right is past the render distance and its margin
+        world.current_chunk_coord = ChunkPos::new(-4, 0);
+        world.remove_far_chunks();
+        world.update_meshes(&BlockRegistry::new());
+        assert!(world.get_chunk(&ChunkPos::new(1, 0)).is_none());
+        assert_eq!(face_count(&world), 6);
+    }
+
+    #[test]
+    fn sections_under_the_ground_are_not_visible() {
+        let dirt = BlockRegistry::new().get_id("dirt").unwrap();
+        let mut world = world_with_chunks(&[ChunkPos::new(0, 0)]);
+        // The three bottom sections are solid
+        for x in 0..16 {
+            for y in BlockPos::MIN_Y..BlockPos::MIN_Y + 48 {
+                for z in 0..16 {
+                    world.set_block(BlockPos::new(x, y, z), dirt);
+                }
+            }
+        }
+        world.update_meshes(&BlockRegistry::new());
+
+        let visible_sections = world
+            .get_visible_sections(&Point3::new(8.0, 40.0, 8.0), |_| true)
+            .unwrap();
+        let mut section_ys = visible_sections
+            .iter()
+            .map(|pos| pos.y)
+            .collect::<Vec<i32>>();
+        section_ys.sort();
+        // The top of the ground is seen, not what is below
+        assert_eq!(section_ys, [2, 3, 4, 5, 6, 7]);
+
+        assert!(world
+            .get_visible_sections(&Point3::new(100.0, 40.0, 8.0), |_| true)
+            .is_none());
+    }
+
+    #[test]
+    fn lod_changes_with_hysteresis() {
+        let lod = BoxWorld::get_lod_for_distance;
+        assert_eq!(lod(4, 1.0, None), 1);
+        assert_eq!(lod(4, 4.0, None), 2);
+        assert_eq!(lod(4, 9.0, None), 4);
+        assert_eq!(lod(4, 30.0, None), 8);
+        assert_eq!(lod(0, 30.0, None), 1);
+
+        // Coarser right away, finer only a chunk past the limit
+        assert_eq!(lod(4, 4.0, Some(1)), 2);
+        assert_eq!(lod(4, 3.5, Some(2)), 2);
+        assert_eq!(lod(4, 2.5, Some(2)), 1);
+        assert_eq!(lod(4, 7.5, Some(4)), 4);
+        assert_eq!(lod(4, 5.0, Some(4)), 2);
+
+        let mut world = world_with_chunks(&[ChunkPos::new(0, 0), ChunkPos::new(5, 0)]);
+        assert_eq!(world.get_chunk(&ChunkPos::new(5, 0)).unwrap().get_lod(), 2);
+        world.current_chunk_coord = ChunkPos::new(3, 0);
+        world.update_lods();
+        assert_eq!(world.get_chunk(&ChunkPos::new(5, 0)).unwrap().get_lod(), 1);
+        assert!(world.get_chunk(&ChunkPos::new(5, 0)).unwrap().is_dirty());
+        assert_eq!(world.get_chunk(&ChunkPos::new(0, 0)).unwrap().get_lod(), 1);
+    }
+
+    #[test]
+    fn closest_chunks_in_view_are_loaded_first() {
+        let mut world = world_with_chunks(&[ChunkPos::new(0, 0)]);
+        // Looking toward +x
+        world.update_load_queue(&Vector2::new(1.0, 0.0));
+
+        assert!(!world.load_queue.contains(&ChunkPos::new(0, 0)));
+        assert!(!world.load_queue.contains(&ChunkPos::new(3, 3)));
+        let position = |chunk_coord: ChunkPos| {
+            world
+                .load_queue
+                .iter()
+                .position(|coord| *coord == chunk_coord)
+                .unwrap()
+        };
+        // The queue is popped from its end
+        assert!(position(ChunkPos::new(1, 0)) > position(ChunkPos::new(2, 0)));
+        assert!(position(ChunkPos::new(2, 0)) > position(ChunkPos::new(-2, 0)));
+        assert!(position(ChunkPos::new(-1, 0)) > position(ChunkPos::new(3, 0)));
+
+        // Moving away replaces the queued chunks out of range
+        world.current_chunk_coord = ChunkPos::new(10, 0);
+        world.update_load_queue(&Vector2::new(1.0, 0.0));
+        assert!(!world.load_queue.contains(&ChunkPos::new(-1, 0)));
+        assert_eq!(*world.load_queue.last().unwrap(), ChunkPos::new(10, 0));
+    }
+
+    #[test]
+    fn render_distance_change_loads_and_unloads_chunks() {
+        let mut world = world_with_chunks(&[
+            ChunkPos::new(0, 0),
+            ChunkPos::new(2, 0),
+            ChunkPos::new(3, 0),
+        ]);
+        assert!(!world.take_range_changed());
+
+        world.set_render_distance(1);
+        assert!(world.take_range_changed());
+        assert!(!world.take_range_changed());
+        world.remove_far_chunks();
+        // The chunk just past the render distance is kept until it is past the margin too
+        assert!(world.get_chunk(&ChunkPos::new(2, 0)).is_some());
+        assert!(world.get_chunk(&ChunkPos::new(3, 0)).is_none());
+
+        world.set_render_distance(4);
+        world.update_load_queue(&Vector2::new(1.0, 0.0));
+        assert!(world.load_queue.contains(&ChunkPos::new(4, 0)));
+        assert!(world.load_queue.contains(&ChunkPos::new(3, 0)));
+        assert!(!world.load_queue.contains(&ChunkPos::new(2, 0)));
+        assert!(!world.load_queue.contains(&ChunkPos::new(4, 1)));
+
+        world.set_render_distance(0);
+        assert_eq!(world.get_render_distance(), 1);
+
+        // Out of range in the settings too
+        let registry = BlockRegistry::new();
+        for (render_distance, clamped) in [(0, 1), (500, 32)] {
+            let config = Config {
+                render_distance,
+                ..Config::default()
+            };
+            let world = BoxWorld::new(&config, &registry).unwrap();
+            assert_eq!(world.get_render_distance(), clamped);
+        }
+    }
 }
@@ -0,0 +1,384 @@
+use crate::boxworld::block::{BlockFace, RawModelFaceInstance};
+use crate::boxworld::state::BlockRotation;
+use nalgebra::{Vector2, Vector3};
+use serde::Deserialize;
+
+// Models are laid out in sixteenths of a block, and their textures in sixteenths of a tile
+pub const MODEL_SIZE: u8 = 16;
+
+// Part of the tile drawn on a quad: left, top, right and bottom
+pub type ModelUv = (u8, u8, u8, u8);
+
+const FULL_UV: ModelUv = (0, 0, MODEL_SIZE, MODEL_SIZE);
+
+// The right and up directions of the faces, as vertex.vert orients the faces of the cubes so
+// both kinds of faces show their texture the same way
+pub const FACE_ORIENTATIONS: [(BlockFace, [i8; 3], [i8; 3]); 6] = [
+    (BlockFace::FRONT, [1, 0, 0], [0, 1, 0]),
+    (BlockFace::BACK, [-1, 0, 0], [0, 1, 0]),
+    (BlockFace::RIGHT, [0, 0, -1], [0, 1, 0]),
+    (BlockFace::LEFT, [0, 0, 1], [0, 1, 0]),
+    (BlockFace::TOP, [1, 0, 0], [0, 0, -1]),
+    (BlockFace::BOTTOM, [1, 0, 0], [0, 0, 1]),
+];
+
+// Texture of each face of a box, the part matching the position of the face by default
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FaceUvs {
+    #[serde(default)]
+    pub front: Option<ModelUv>,
+    #[serde(default)]
+    pub back: Option<ModelUv>,
+    #[serde(default)]
+    pub right: Option<ModelUv>,
+    #[serde(default)]
+    pub left: Option<ModelUv>,
+    #[serde(default)]
+    pub top: Option<ModelUv>,
+    #[serde(default)]
+    pub bottom: Option<ModelUv>,
+}
+
+impl FaceUvs {
+    fn get(&self, face: &BlockFace) -> Option<ModelUv> {
+        match *face {
+            BlockFace::FRONT => self.front,
+            BlockFace::BACK => self.back,
+            BlockFace::RIGHT => self.right,
+            BlockFace::LEFT => self.left,
+            BlockFace::TOP => self.top,
+            _ => self.bottom,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum ModelElement {
+    // Axis aligned box between its lowest and highest corners
+    Box {
+        from: (u8, u8, u8),
+        to: (u8, u8, u8),
+        #[serde(default)]
+        uvs: FaceUvs,
+    },
+    // Two quads crossing along the diagonals of the block, seen from both sides, for plants
+    Cross {
+        #[serde(default)]
+        uv: Option<ModelUv>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelDefinition {
+    elements: Vec<ModelElement>,
+}
+
+// Shape of a block, made of boxes and crossed quads
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "ModelDefinition")]
+pub struct BlockModel {
+    elements: Vec<ModelElement>,
+    // Sides of the block the model covers completely, the only ones hiding the faces against them
+    full_faces: BlockFace,
+    // A single box filling the block with the default textures
+    is_cube: bool,
+}
+
+impl From<ModelDefinition> for BlockModel {
+    fn from(definition: ModelDefinition) -> Self {
+        Self::new(definition.elements)
+    }
+}
+
+impl BlockModel {
+    pub fn new(elements: Vec<ModelElement>) -> Self {
+        let mut full_faces = BlockFace::empty();
+        for element in elements.iter() {
+            let ModelElement::Box { from, to, .. } = element else {
+                continue;
+            };
+            let (from, to) = (
+                Vector3::new(from.0, from.1, from.2),
+                Vector3::new(to.0, to.1, to.2),
+            );
+            for (face, right, up) in FACE_ORIENTATIONS.iter() {
+                let (normal, is_positive) = get_normal(right, up);
+                let on_side = if is_positive {
+                    to[normal] == MODEL_SIZE
+                } else {
+                    from[normal] == 0
+                };
+                let covers_side = (0..3)
+                    .filter(|axis| *axis != normal)
+                    .all(|axis| from[axis] == 0 && to[axis] == MODEL_SIZE);
+                if on_side && covers_side {
+                    full_faces |= face.clone();
+                }
+            }
+        }
+        let is_cube = matches!(
+            elements.as_slice(),
+            [ModelElement::Box { from: (0, 0, 0), to: (MODEL_SIZE, MODEL_SIZE, MODEL_SIZE), uvs }]
+                if *uvs == FaceUvs::default()
+        );
+        Self {
+            elements,
+            full_faces,
+            is_cube,
+        }
+    }
+
+    pub fn cube() -> Self {
+        Self::new(vec![ModelElement::Box {
+            from: (0, 0, 0),
+            to: (MODEL_SIZE, MODEL_SIZE, MODEL_SIZE),
+            uvs: FaceUvs::default(),
+        }])
+    }
+
+    // A cube cut at `height` sixteenths of a block, for the surface of fluids
+    pub fn with_height(height: u8) -> Self {
+        Self::new(vec![ModelElement::Box {
+            from: (0, 0, 0),
+            to: (MODEL_SIZE, height, MODEL_SIZE),
+            uvs: FaceUvs::default(),
+        }])
+    }
+
+    // Meshed with the greedy mesher instead of face by face
+    pub fn is_cube(&self) -> bool {
+        self.is_cube
+    }
+
+    pub fn get_full_faces(&self) -> &BlockFace {
+        &self.full_faces
+    }
+
+    pub fn get_elements(&self) -> &Vec<ModelElement> {
+        &self.elements
+    }
+
+    // Quads of the model for the block at the position inside its chunk, turned by the rotation of
+    // its state. The faces lying on a side of the block are only drawn when the side is
+    // `uncovered`, the others are always drawn. Each face takes the texture of the side of the model
+    // it comes from.
+    pub fn mesh(
+        &self,
+        position: Vector3<usize>,
+        rotation: &BlockRotation,
+        uncovered: &BlockFace,
+        get_texture_pos: impl Fn(&BlockFace) -> Vector2<i32>,
+        raw_model_face_instances: &mut Vec<RawModelFaceInstance>,
+    ) {
+        let mut push = |origin: Vector3<u8>, right: [i8; 3], up: [i8; 3], uv, texture_pos| {
+            raw_model_face_instances.push(RawModelFaceInstance::new(
+                position,
+                origin,
+                Vector3::from(right),
+                Vector3::from(up),
+                uv,
+                texture_pos,
+                RawModelFaceInstance::MAX_LIGHT,
+            ));
+        };
+
+        for element in self.elements.iter() {
+            match element {
+                ModelElement::Box { from, to, uvs } => {
+                    let (from, to) = rotation.rotate_box(
+                        Vector3::new(from.0, from.1, from.2),
+                        Vector3::new(to.0, to.1, to.2),
+                    );
+                    for (face, right, up) in FACE_ORIENTATIONS.iter() {
+                        let (normal, is_positive) = get_normal(right, up);
+                        let on_side = if is_positive {
+                            to[normal] == MODEL_SIZE
+                        } else {
+                            from[normal] == 0
+                        };
+                        if on_side && !uncovered.contains(face.clone()) {
+                            continue;
+                        }
+
+                        // The quad starts at the corner its right and up directions leave from
+                        let mut origin = Vector3::zeros();
+                        origin[normal] = if is_positive {
+                            to[normal]
+                        } else {
+                            from[normal]
+                        };
+                        let mut quad = [[0i8; 3]; 2];
+                        let mut spans = [(0, 0); 2];
+                        for (index, direction) in [right, up].into_iter().enumerate() {
+                            let axis = direction.iter().position(|d| *d != 0).unwrap();
+                            let extent = to[axis] - from[axis];
+                            let start = if direction[axis] > 0 {
+                                origin[axis] = from[axis];
+                                from[axis]
+                            } else {
+                                origin[axis] = to[axis];
+                                MODEL_SIZE - to[axis]
+                            };
+                            quad[index][axis] = direction[axis] * extent as i8;
+                            spans[index] = (start, start + extent);
+                        }
+                        if spans.iter().any(|(start, end)| start == end) {
+                            continue;
+                        }
+
+                        // The texture is mirrored along both directions, as on the cubes
+                        let [(right_start, right_end), (up_start, up_end)] = spans;
+                        let model_face = rotation.to_model_face(face);
+                        let uv = uvs.get(&model_face).unwrap_or((
+                            MODEL_SIZE - right_end,
+                            MODEL_SIZE - up_end,
+                            MODEL_SIZE - right_start,
+                            MODEL_SIZE - up_start,
+                        ));
+                        push(origin, quad[0], quad[1], uv, get_texture_pos(&model_face));
+                    }
+                }
+                // Symmetric enough to never be turned
+                ModelElement::Cross { uv } => {
+                    let uv = uv.unwrap_or(FULL_UV);
+                    let texture_pos = get_texture_pos(&BlockFace::FRONT);
+                    let (size, side) = (MODEL_SIZE, MODEL_SIZE as i8);
+                    let up = [0, side, 0];
+                    // Each diagonal once from each side
+                    push(Vector3::new(0, 0, 0), [side, 0, side], up, uv, texture_pos);
+                    push(
+                        Vector3::new(size, 0, size),
+                        [-side, 0, -side],
+                        up,
+                        uv,
+                        texture_pos,
+                    );
+                    push(
+                        Vector3::new(size, 0, 0),
+                        [-side, 0, side],
+                        up,
+                        uv,
+                        texture_pos,
+                    );
+                    push(
+                        Vector3::new(0, 0, size),
+                        [side, 0, -side],
+                        up,
+                        uv,
+                        texture_pos,
+                    );
+                }
+            }
+        }
+    }
+}
+
+// Axis of the normal of a face, and whether it points toward the positive side
+fn get_normal(right: &[i8; 3], up: &[i8; 3]) -> (usize, bool) {
+    let normal = Vector3::from(*right)
+        .cast::<i32>()
+        .cross(&Vector3::from(*up).cast::<i32>());
+    let axis = normal.iter().position(|n| *n != 0).unwrap();
+    (axis, normal[axis] > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BlockModel, FaceUvs, ModelElement};
+    use crate::boxworld::block::BlockFace;
+    use crate::boxworld::state::{BlockRotation, BlockState, Half};
+    use nalgebra::{Vector2, Vector3};
+
+    fn slab() -> BlockModel {
+        BlockModel::new(vec![ModelElement::Box {
+            from: (0, 0, 0),
+            to: (16, 8, 16),
+            uvs: FaceUvs::default(),
+        }])
+    }
+
+    #[test]
+    fn only_full_sides_are_covered() {
+        assert_eq!(*BlockModel::cube().get_full_faces(), BlockFace::all());
+        assert!(BlockModel::cube().is_cube());
+
+        let slab = slab();
+        assert_eq!(*slab.get_full_faces(), BlockFace::BOTTOM);
+        assert!(!slab.is_cube());
+
+        let plant = BlockModel::new(vec![ModelElement::Cross { uv: None }]);
+        assert!(plant.get_full_faces().is_empty());
+    }
+
+    #[test]
+    fn boxes_are_meshed_with_the_matching_texture_part() {
+        let mut raw_model_face_instances = Vec::new();
+        slab().mesh(
+            Vector3::new(1, 2, 3),
+            &BlockRotation::identity(),
+            &(BlockFace::all() - BlockFace::BOTTOM),
+            |_| Vector2::new(3, 0),
+            &mut raw_model_face_instances,
+        );
+
+        // The bottom lies against a covering block, the top is inside the block
+        assert_eq!(raw_model_face_instances.len(), 5);
+        let top = raw_model_face_instances
+            .iter()
+            .find(|instance| instance.get_up() == Vector3::new(0, 0, -16))
+            .unwrap();
+        assert_eq!(top.get_position(), Vector3::new(1, 2, 3));
+        assert_eq!(top.get_origin(), Vector3::new(0, 8, 16));
+        assert_eq!(top.get_right(), Vector3::new(16, 0, 0));
+        assert_eq!(top.get_uv(), (0, 0, 16, 16));
+
+        // The sides show the bottom half of the tile
+        let front = raw_model_face_instances
+            .iter()
+            .find(|instance| instance.get_right() == Vector3::new(16, 0, 0) && instance != &top)
+            .unwrap();
+        assert_eq!(front.get_origin(), Vector3::new(0, 0, 16));
+        assert_eq!(front.get_up(), Vector3::new(0, 8, 0));
+        assert_eq!(front.get_uv(), (0, 8, 16, 16));
+        assert_eq!(front.get_texture_pos(), Vector2::new(3, 0));
+
+        // In the top half the sides show the top half of the tile, and the top is against the
+        // covering block
+        raw_model_face_instances.clear();
+        slab().mesh(
+            Vector3::new(1, 2, 3),
+            &BlockState::default().with_half(Half::Top).get_rotation(),
+            &(BlockFace::all() - BlockFace::TOP),
+            |face| Vector2::new(3, (*face == BlockFace::BOTTOM) as i32),
+            &mut raw_model_face_instances,
+        );
+        assert_eq!(raw_model_face_instances.len(), 5);
+        let front = raw_model_face_instances
+            .iter()
+            .find(|instance| instance.get_right() == Vector3::new(16, 0, 0))
+            .unwrap();
+        assert_eq!(front.get_origin(), Vector3::new(0, 8, 16));
+        assert_eq!(front.get_uv(), (0, 0, 16, 8));
+        // The bottom of the block is the top of the model
+        let bottom = raw_model_face_instances
+            .iter()
+            .find(|instance| instance.get_up() == Vector3::new(0, 0, 16))
+            .unwrap();
+        assert_eq!(bottom.get_origin(), Vector3::new(0, 8, 0));
+        assert_eq!(bottom.get_texture_pos(), Vector2::new(3, 0));
+
+        // Both sides of both diagonals
+        raw_model_face_instances.clear();
+        BlockModel::new(vec![ModelElement::Cross { uv: None }]).mesh(
+            Vector3::new(0, 0, 0),
+            &BlockRotation::identity(),
+            &BlockFace::empty(),
+            |_| Vector2::new(0, 3),
+            &mut raw_model_face_instances,
+        );
+        assert_eq!(raw_model_face_instances.len(), 4);
+    }
+}
@@ -0,0 +1,199 @@
+// Fixed-size array of values stored as bit-packed indices into a palette of the distinct values.
+// A uniform storage has a single palette entry and no index data at all.
+#[derive(Clone, Debug)]
+pub struct PalettedStorage<T> {
+    len: usize,
+    palette: Vec<T>,
+    bits_per_index: u32,
+    data: Vec<u64>,
+}
+
+impl<T: Copy + PartialEq> PalettedStorage<T> {
+    pub fn new(len: usize, value: T) -> Self {
+        Self {
+            len,
+            palette: vec![value],
+            bits_per_index: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn from_raw(
+        len: usize,
+        palette: Vec<T>,
+        bits_per_index: u32,
+        data: Vec<u64>,
+    ) -> Option<Self> {
+        let storage = Self {
+            len,
+            palette,
+            bits_per_index,
+            data,
+        };
+        let is_valid = !storage.palette.is_empty()
+            && storage.bits_per_index <= u16::BITS
+            && storage.palette.len() <= 1 << storage.bits_per_index
+            && storage.data.len() == Self::get_word_count(len, bits_per_index)
+            && (0..len).all(|i| storage.get_palette_index(i) < storage.palette.len());
+        is_valid.then_some(storage)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get_palette(&self) -> &[T] {
+        &self.palette
+    }
+
+    pub fn get_bits_per_index(&self) -> u32 {
+        self.bits_per_index
+    }
+
+    pub fn get_data(&self) -> &[u64] {
+        &self.data
+    }
+
+    // Whether every value is the same, without looking at the indices when the palette allows it
+    pub fn get_uniform(&self) -> Option<T> {
+        if self.palette.len() == 1 {
+            return Some(self.palette[0]);
+        }
+        let first = self.get(0);
+        (1..self.len).all(|i| self.get(i) == first).then_some(first)
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> T {
+        debug_assert!(index < self.len);
+        self.palette[self.get_palette_index(index)]
+    }
+
+    pub fn set(&mut self, index: usize, value: T) {
+        debug_assert!(index < self.len);
+        let palette_index = match self.palette.iter().position(|v| *v == value) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(value);
+                if self.palette.len() > 1 << self.bits_per_index {
+                    self.resize(self.bits_per_index + 1);
+                }
+                self.palette.len() - 1
+            }
+        };
+        self.set_palette_index(index, palette_index);
+    }
+
+    pub fn fill(&mut self, value: T) {
+        *self = Self::new(self.len, value);
+    }
+
+    // Drops the palette entries no index refers to anymore, shrinking the indices if possible
+    pub fn compact(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for i in 0..self.len {
+            used[self.get_palette_index(i)] = true;
+        }
+        if used.iter().all(|used| *used) {
+            return;
+        }
+
+        let values = (0..self.len).map(|i| self.get(i)).collect::<Vec<T>>();
+        let mut compacted = Self::new(self.len, values[0]);
+        for (i, value) in values.into_iter().enumerate() {
+            compacted.set(i, value);
+        }
+        *self = compacted;
+    }
+
+    #[inline]
+    fn get_indices_per_word(bits_per_index: u32) -> usize {
+        (u64::BITS / bits_per_index) as usize
+    }
+
+    pub fn get_word_count(len: usize, bits_per_index: u32) -> usize {
+        if bits_per_index == 0 {
+            0
+        } else {
+            len.div_ceil(Self::get_indices_per_word(bits_per_index))
+        }
+    }
+
+    #[inline]
+    fn get_palette_index(&self, index: usize) -> usize {
+        if self.bits_per_index == 0 {
+            return 0;
+        }
+        let indices_per_word = Self::get_indices_per_word(self.bits_per_index);
+        let word = self.data[index / indices_per_word];
+        let shift = (index % indices_per_word) as u32 * self.bits_per_index;
+        ((word >> shift) & ((1 << self.bits_per_index) - 1)) as usize
+    }
+
+    #[inline]
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        if self.bits_per_index == 0 {
+            return;
+        }
+        let indices_per_word = Self::get_indices_per_word(self.bits_per_index);
+        let mask = (1u64 << self.bits_per_index) - 1;
+        let shift = (index % indices_per_word) as u32 * self.bits_per_index;
+        let word = &mut self.data[index / indices_per_word];
+        *word = (*word & !(mask << shift)) | ((palette_index as u64 & mask) << shift);
+    }
+
+    fn resize(&mut self, bits_per_index: u32) {
+        let palette_indices = (0..self.len)
+            .map(|i| self.get_palette_index(i))
+            .collect::<Vec<usize>>();
+
+        self.bits_per_index = bits_per_index;
+        self.data = vec![0; Self::get_word_count(self.len, bits_per_index)];
+        for (i, palette_index) in palette_indices.into_iter().enumerate() {
+            self.set_palette_index(i, palette_index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PalettedStorage;
+
+    #[test]
+    fn values_survive_palette_growth() {
+        let mut storage = PalettedStorage::new(4096, 0u16);
+        assert!(storage.get_data().is_empty());
+
+        for i in 0..4096 {
+            storage.set(i, (i % 300) as u16);
+        }
+        assert_eq!(storage.get_palette().len(), 300);
+        assert_eq!(storage.get_bits_per_index(), 9);
+        assert!((0..4096).all(|i| storage.get(i) == (i % 300) as u16));
+    }
+
+    #[test]
+    fn compact_drops_unused_values() {
+        let mut storage = PalettedStorage::new(64, 0u8);
+        storage.set(3, 1);
+        storage.set(4, 2);
+        storage.set(3, 0);
+        storage.set(4, 0);
+        assert_eq!(storage.get_uniform(), Some(0));
+
+        storage.compact();
+        assert_eq!(storage.get_palette(), &[0]);
+        assert_eq!(storage.get_bits_per_index(), 0);
+    }
+
+    #[test]
+    fn invalid_raw_storage_is_rejected() {
+        assert!(PalettedStorage::from_raw(4, vec![0u8, 1], 1, vec![0b0110]).is_some());
+        assert!(PalettedStorage::from_raw(4, vec![0u8, 1], 1, vec![]).is_none());
+        assert!(PalettedStorage::<u8>::from_raw(4, vec![0, 1, 2], 1, vec![0]).is_none());
+    }
+}
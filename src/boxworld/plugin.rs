@@ -1,21 +1,65 @@
+use crate::app::error::AppError;
+use crate::boxworld::registry::BlockRegistry;
+use crate::boxworld::systems::mesh::update_meshes;
 use crate::boxworld::systems::sync_camera::sync_camera;
+use crate::boxworld::systems::tick::tick_blocks;
 use crate::boxworld::systems::worker::{calculate, update_worker};
+use crate::boxworld::tick::BlockTicks;
 use crate::boxworld::BoxWorld;
+use crate::game::camera::{Camera, CameraPlugin};
+use crate::game::config::Config;
 use crate::game::schedule::ScheduleStage;
-use crate::plugin::Plugin;
+use crate::plugin::{Plugin, RequiredResource};
 use bevy_ecs::prelude::*;
 use winit::window::Window;
 
 pub struct WorldPlugin;
 
+impl WorldPlugin {
+    pub const LABEL: &'static str = "world";
+}
+
 impl Plugin for WorldPlugin {
-    fn register_init(&self, world: &mut World, _schedule: &mut Schedule, _window: &Window) {
-        world.insert_resource(BoxWorld::new());
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[CameraPlugin::LABEL]
+    }
+
+    fn required_resources(&self) -> Vec<RequiredResource> {
+        vec![
+            RequiredResource::of::<Config>(),
+            RequiredResource::of::<Camera>(),
+        ]
+    }
+
+    fn register_init(
+        &self,
+        world: &mut World,
+        _schedule: &mut Schedule,
+        _window: Option<&Window>,
+    ) -> Result<(), AppError> {
+        let registry = BlockRegistry::load(BlockRegistry::PATH, BlockRegistry::MODELS_PATH)
+            .map_err(AppError::Registry)?;
+        let box_world =
+            BoxWorld::new(world.resource::<Config>(), &registry).map_err(AppError::World)?;
+        let block_ticks = BlockTicks::with_block_handlers(
+            world.resource::<Config>().random_ticks_per_section,
+            box_world.get_seed(),
+            &registry,
+        );
+        world.insert_resource(box_world);
+        world.insert_resource(block_ticks);
+        world.insert_resource(registry);
+        Ok(())
     }
 
     fn register_runtime(&self, _world: &mut World, schedule: &mut Schedule) {
         schedule
             .add_systems((calculate, update_worker))
-            .add_systems(sync_camera.in_set(ScheduleStage::PostUpdate));
+            .add_systems(tick_blocks.in_set(ScheduleStage::Update))
+            .add_systems((sync_camera, update_meshes).in_set(ScheduleStage::PostUpdate));
     }
 }
@@ -0,0 +1,200 @@
+use crate::boxworld::block::Block;
+use crate::boxworld::chunk::Chunk;
+use nalgebra::{Point3, Vector2};
+use std::ops::Sub;
+
+// Integer coordinate of a block in the world, the block spans [x, x + 1) on every axis
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    // Chunks are centered vertically around y = 0
+    pub const MIN_Y: i32 = -(Chunk::CHUNK_VERTICAL_BLOCK as i32 / 2);
+    pub const MAX_Y: i32 = Self::MIN_Y + Chunk::CHUNK_VERTICAL_BLOCK as i32 - 1;
+
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn from_world_coord(world_coord: &Point3<f32>) -> Self {
+        let block_coord = world_coord / Block::SIZE;
+        Self::new(
+            block_coord.x.floor() as i32,
+            block_coord.y.floor() as i32,
+            block_coord.z.floor() as i32,
+        )
+    }
+
+    pub fn from_local(chunk_coord: &ChunkPos, local: LocalPos) -> Self {
+        chunk_coord
+            .get_origin()
+            .offset(local.x as i32, local.y as i32, local.z as i32)
+    }
+
+    // World position of the lowest corner of the block
+    pub fn get_world_coord(&self) -> Point3<f32> {
+        Point3::new(self.x, self.y, self.z).cast::<f32>() * Block::SIZE
+    }
+
+    pub fn offset(&self, x: i32, y: i32, z: i32) -> Self {
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+
+    pub fn get_chunk_coord(&self) -> ChunkPos {
+        let side = Chunk::CHUNK_SIDE_BLOCK as i32;
+        ChunkPos::new(self.x.div_euclid(side), self.z.div_euclid(side))
+    }
+
+    // Coordinate inside its chunk, none above or below the chunk
+    pub fn get_local(&self) -> Option<LocalPos> {
+        if !(Self::MIN_Y..=Self::MAX_Y).contains(&self.y) {
+            return None;
+        }
+        let side = Chunk::CHUNK_SIDE_BLOCK as i32;
+        Some(LocalPos::new(
+            self.x.rem_euclid(side) as usize,
+            (self.y - Self::MIN_Y) as usize,
+            self.z.rem_euclid(side) as usize,
+        ))
+    }
+}
+
+// Coordinate of a chunk, in chunks along x and z
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+
+    pub fn from_world_coord(world_coord: &Point3<f32>) -> Self {
+        BlockPos::from_world_coord(world_coord).get_chunk_coord()
+    }
+
+    pub fn offset(&self, x: i32, z: i32) -> Self {
+        Self::new(self.x + x, self.z + z)
+    }
+
+    // Lowest block of the chunk
+    pub fn get_origin(&self) -> BlockPos {
+        let side = Chunk::CHUNK_SIDE_BLOCK as i32;
+        BlockPos::new(self.x * side, BlockPos::MIN_Y, self.z * side)
+    }
+
+    // Distance in chunks
+    pub fn distance(&self, other: &ChunkPos) -> f32 {
+        (*self - *other).cast::<f32>().norm()
+    }
+}
+
+// Offset in chunks from the other chunk, on x and z
+impl Sub for ChunkPos {
+    type Output = Vector2<i32>;
+
+    fn sub(self, other: Self) -> Self::Output {
+        Vector2::new(self.x - other.x, self.z - other.z)
+    }
+}
+
+// Coordinate of a block inside its chunk, y counted from the bottom of the chunk
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct LocalPos {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+impl LocalPos {
+    pub fn new(x: usize, y: usize, z: usize) -> Self {
+        debug_assert!(x < Chunk::CHUNK_SIDE_BLOCK && z < Chunk::CHUNK_SIDE_BLOCK);
+        debug_assert!(y < Chunk::CHUNK_VERTICAL_BLOCK);
+        Self { x, y, z }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BlockPos, ChunkPos, LocalPos};
+    use crate::boxworld::chunk::Chunk;
+    use nalgebra::{Point3, Vector2, Vector3};
+
+    #[test]
+    fn negative_coordinates_round_down() {
+        let pos = BlockPos::from_world_coord(&Point3::new(-0.5, -64.0, 16.0));
+        assert_eq!(pos, BlockPos::new(-1, -64, 16));
+        assert_eq!(pos.get_chunk_coord(), ChunkPos::new(-1, 1));
+        assert_eq!(pos.get_local(), Some(LocalPos::new(15, 0, 0)));
+        assert_eq!(
+            BlockPos::from_local(&pos.get_chunk_coord(), LocalPos::new(15, 0, 0)),
+            pos
+        );
+
+        assert_eq!(BlockPos::new(0, BlockPos::MAX_Y + 1, 0).get_local(), None);
+        assert_eq!(BlockPos::new(0, BlockPos::MIN_Y - 1, 0).get_local(), None);
+    }
+
+    #[test]
+    fn world_coordinates_around_chunk_borders() {
+        let cases = [
+            (-32.0, -2),
+            (-16.001, -2),
+            (-16.0, -1),
+            (-15.999, -1),
+            (-0.001, -1),
+            (-0.0, 0),
+            (0.0, 0),
+            (15.999, 0),
+            (16.0, 1),
+        ];
+        for (coord, chunk) in cases {
+            let world_coord = Point3::new(coord, 0.0, coord);
+            assert_eq!(
+                ChunkPos::from_world_coord(&world_coord),
+                ChunkPos::new(chunk, chunk),
+                "{}",
+                coord
+            );
+        }
+    }
+
+    #[test]
+    fn block_positions_round_trip_through_chunks() {
+        let side = Chunk::CHUNK_SIDE_BLOCK as i32;
+        for x in -3 * side..3 * side {
+            for y in [BlockPos::MIN_Y, -1, 0, BlockPos::MAX_Y] {
+                let pos = BlockPos::new(x, y, -x - 1);
+                let chunk_coord = pos.get_chunk_coord();
+                let local = pos.get_local().unwrap();
+                assert_eq!(BlockPos::from_local(&chunk_coord, local), pos);
+
+                // The block lies inside its chunk, whose origin is a multiple of the side
+                let origin = chunk_coord.get_origin();
+                assert_eq!(origin.x.rem_euclid(side), 0);
+                assert!((origin.x..origin.x + side).contains(&pos.x));
+                assert!((origin.z..origin.z + side).contains(&pos.z));
+
+                // Any point of the block is in the same block and chunk
+                let world_coord = pos.get_world_coord() + Vector3::from_element(0.5);
+                assert_eq!(BlockPos::from_world_coord(&world_coord), pos);
+                assert_eq!(
+                    ChunkPos::from_world_coord(&pos.get_world_coord()),
+                    chunk_coord
+                );
+            }
+        }
+
+        assert_eq!(
+            ChunkPos::new(-1, 2) - ChunkPos::new(1, -1),
+            Vector2::new(-2, 3)
+        );
+        assert_eq!(ChunkPos::new(-3, 0).distance(&ChunkPos::new(0, 4)), 5.0);
+    }
+}
@@ -0,0 +1,444 @@
+use crate::boxworld::block::{Block, BlockFace};
+use crate::boxworld::fluid::FluidDefinition;
+use crate::boxworld::model::BlockModel;
+use crate::boxworld::state::StateProperty;
+use bevy_ecs::prelude::*;
+use nalgebra::Vector2;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::Path;
+
+// Identifier chunks store for a block, saved worlds rely on it never changing
+pub type BlockId = u16;
+
+#[derive(Debug, PartialEq)]
+pub enum RegistryError {
+    IOError,
+    ParseError(ron::error::SpannedError),
+    InvalidId(BlockId),
+    DuplicateId(BlockId),
+    DuplicateName(String),
+    UnknownModel(String),
+    // A block the game needs by name
+    MissingBlock(String),
+}
+
+// Tiles of the atlas as (column, row), the top and the bottom default to the side
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BlockTextures {
+    pub side: (u8, u8),
+    #[serde(default)]
+    pub top: Option<(u8, u8)>,
+    #[serde(default)]
+    pub bottom: Option<(u8, u8)>,
+}
+
+// Atlas tile drawn on a face of a cube, turned by quarter turns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaceTexture {
+    pub pos: Vector2<i32>,
+    pub turns: u8,
+}
+
+// How the faces of the block are drawn
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderLayer {
+    // Hides what is behind it
+    #[default]
+    Opaque,
+    // Either fully transparent or opaque, the transparent texels are discarded
+    Cutout,
+    // Blended over what is behind it, drawn last from back to front
+    Translucent,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BlockDefinition {
+    pub id: BlockId,
+    pub name: String,
+    pub textures: BlockTextures,
+    // Name of the shape in the models file
+    #[serde(default = "default_model")]
+    pub model: String,
+    // What the state of the block can tell, set when the block is placed
+    #[serde(default)]
+    pub properties: Vec<StateProperty>,
+    // Takes up the whole cube
+    #[serde(default = "default_true")]
+    pub solid: bool,
+    #[serde(default)]
+    pub layer: RenderLayer,
+    // Stops the player
+    #[serde(default = "default_true")]
+    pub collidable: bool,
+    // Flows from the placed blocks, which are its sources
+    #[serde(default)]
+    pub fluid: Option<FluidDefinition>,
+    // Seconds to break it by hand
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+fn default_model() -> String {
+    "cube".to_string()
+}
+
+// Every block that can exist in the world, read from a RON list of BlockDefinition, and the shapes
+// they use, read from a RON map of BlockModel by name
+#[derive(Resource, Clone, Debug)]
+pub struct BlockRegistry {
+    // Indexed by id
+    blocks: Vec<Option<BlockDefinition>>,
+    models: Vec<BlockModel>,
+    ids: HashMap<String, BlockId>,
+    // Shape of the blocks missing from the registry
+    cube: BlockModel,
+}
+
+impl BlockRegistry {
+    pub const PATH: &'static str = "assets/blocks.ron";
+    pub const MODELS_PATH: &'static str = "assets/models.ron";
+    // The blocks and models shipped with the game, used when there is no file at the given path
+    const DEFAULT_BLOCKS: &'static str = include_str!("../../assets/blocks.ron");
+    const DEFAULT_MODELS: &'static str = include_str!("../../assets/models.ron");
+    // Atlas tile drawn for the blocks missing from the registry
+    const MISSING_TEXTURE: Vector2<i32> = Vector2::new(6, 2);
+
+    pub fn new() -> Self {
+        Self::parse(Self::DEFAULT_BLOCKS, Self::DEFAULT_MODELS).unwrap()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, models_path: P) -> Result<Self, RegistryError> {
+        let blocks = Self::read_or(path.as_ref(), Self::DEFAULT_BLOCKS)?;
+        let models = Self::read_or(models_path.as_ref(), Self::DEFAULT_MODELS)?;
+        Self::parse(&blocks, &models)
+    }
+
+    fn read_or(path: &Path, default: &str) -> Result<String, RegistryError> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(content),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                log::info!("No {:?}, using the default one", path);
+                Ok(default.to_string())
+            }
+            Err(_) => Err(RegistryError::IOError),
+        }
+    }
+
+    pub fn parse(content: &str, models: &str) -> Result<Self, RegistryError> {
+        let definitions: Vec<BlockDefinition> =
+            ron::from_str(content).map_err(RegistryError::ParseError)?;
+        let models: HashMap<String, BlockModel> =
+            ron::from_str(models).map_err(RegistryError::ParseError)?;
+        Self::with_definitions(definitions, models)
+    }
+
+    pub fn with_definitions(
+        definitions: Vec<BlockDefinition>,
+        models: HashMap<String, BlockModel>,
+    ) -> Result<Self, RegistryError> {
+        let cube = BlockModel::cube();
+        let mut blocks: Vec<Option<BlockDefinition>> = Vec::new();
+        let mut block_models = Vec::new();
+        let mut ids = HashMap::new();
+        for definition in definitions {
+            // Chunks store the id + 1, 0 being air
+            if definition.id == BlockId::MAX {
+                return Err(RegistryError::InvalidId(definition.id));
+            }
+            let index = definition.id as usize;
+            if blocks.len() <= index {
+                blocks.resize(index + 1, None);
+                block_models.resize(index + 1, cube.clone());
+            }
+            if blocks[index].is_some() {
+                return Err(RegistryError::DuplicateId(definition.id));
+            }
+            if ids.insert(definition.name.clone(), definition.id).is_some() {
+                return Err(RegistryError::DuplicateName(definition.name));
+            }
+            let Some(model) = models.get(&definition.model) else {
+                return Err(RegistryError::UnknownModel(definition.model));
+            };
+            block_models[index] = model.clone();
+            blocks[index] = Some(definition);
+        }
+        Ok(Self {
+            blocks,
+            models: block_models,
+            ids,
+            cube,
+        })
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&BlockDefinition> {
+        self.blocks.get(id as usize)?.as_ref()
+    }
+
+    pub fn get_id(&self, name: &str) -> Option<BlockId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    // Unknown blocks are drawn opaque with the missing texture
+    pub fn get_layer(&self, id: BlockId) -> RenderLayer {
+        self.get(id)
+            .map_or(RenderLayer::Opaque, |definition| definition.layer)
+    }
+
+    pub fn get_model(&self, id: BlockId) -> &BlockModel {
+        self.models.get(id as usize).unwrap_or(&self.cube)
+    }
+
+    pub fn get_fluid(&self, id: BlockId) -> Option<&FluidDefinition> {
+        self.get(id)?.fluid.as_ref()
+    }
+
+    pub fn get_fluids(&self) -> impl Iterator<Item = (BlockId, &FluidDefinition)> {
+        self.blocks
+            .iter()
+            .flatten()
+            .filter_map(|definition| Some((definition.id, definition.fluid.as_ref()?)))
+    }
+
+    pub fn get_properties(&self, id: BlockId) -> &[StateProperty] {
+        self.get(id)
+            .map_or(&[], |definition| definition.properties.as_slice())
+    }
+
+    // Nothing can be seen through the block
+    pub fn is_opaque(&self, id: BlockId) -> bool {
+        self.get_layer(id) == RenderLayer::Opaque && self.get_model(id).get_full_faces().is_all()
+    }
+
+    // Whether the side of the block is hidden by the other block against it, whose `other_side`
+    // touches it. Only a side the other block covers completely hides it, if nothing can be seen
+    // through the other block or if both are the same block, like two panes of glass.
+    pub fn is_face_hidden(&self, block: &Block, other: &Block, other_side: &BlockFace) -> bool {
+        // A fluid keeps the side it shows above the surface of the same fluid next to it
+        let is_beside = !matches!(*other_side, BlockFace::TOP | BlockFace::BOTTOM);
+        if let (true, true, Some(fluid)) =
+            (other.id == block.id, is_beside, self.get_fluid(block.id))
+        {
+            return fluid.get_surface_height(other.state.get_level())
+                >= fluid.get_surface_height(block.state.get_level());
+        }
+        let full_faces = self.get_model(other.id).get_full_faces();
+        let is_covered = full_faces.is_all()
+            || full_faces.contains(other.state.get_rotation().to_model_face(other_side));
+        is_covered && (other.id == block.id || self.get_layer(other.id) == RenderLayer::Opaque)
+    }
+
+    // Blocks saved with an id that is no longer registered show a placeholder
+    pub fn get_texture_pos(&self, id: BlockId, face: &BlockFace) -> Vector2<i32> {
+        let Some(definition) = self.get(id) else {
+            return Self::MISSING_TEXTURE;
+        };
+        let textures = &definition.textures;
+        let (x, y) = match *face {
+            BlockFace::TOP => textures.top.unwrap_or(textures.side),
+            BlockFace::BOTTOM => textures.bottom.unwrap_or(textures.side),
+            _ => textures.side,
+        };
+        Vector2::new(x as i32, y as i32)
+    }
+
+    // Texture of the side of a cube, the state decides which side of the block it shows
+    pub fn get_face_texture(&self, block: &Block, face: &BlockFace) -> FaceTexture {
+        let rotation = block.state.get_rotation();
+        FaceTexture {
+            pos: self.get_texture_pos(block.id, &rotation.to_model_face(face)),
+            turns: rotation.get_texture_turns(face),
+        }
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BlockRegistry, FaceTexture, RegistryError, RenderLayer};
+    use crate::boxworld::block::{Block, BlockFace};
+    use crate::boxworld::model::BlockModel;
+    use crate::boxworld::state::{Axis, BlockState, Half, StateProperty};
+    use nalgebra::Vector2;
+
+    #[test]
+    fn default_blocks_are_registered() {
+        let registry = BlockRegistry::new();
+        let grass = registry.get_id("grass").unwrap();
+        assert_eq!(registry.get(grass).unwrap().name, "grass");
+        assert_eq!(
+            registry.get_texture_pos(grass, &BlockFace::TOP),
+            Vector2::new(0, 0)
+        );
+        assert_eq!(
+            registry.get_texture_pos(grass, &BlockFace::LEFT),
+            Vector2::new(1, 0)
+        );
+        assert_eq!(
+            registry.get_layer(registry.get_id("glass").unwrap()),
+            RenderLayer::Cutout
+        );
+        assert_eq!(
+            registry.get_layer(registry.get_id("water").unwrap()),
+            RenderLayer::Translucent
+        );
+        assert!(registry.is_opaque(grass));
+        assert!(registry.get_model(grass).is_cube());
+
+        // A slab only hides what is under it, or above it when it sits in the top half
+        let slab = registry.get_id("stone_slab").unwrap();
+        assert!(!registry.is_opaque(slab));
+        let (grass, slab) = (Block::new(grass), Block::new(slab));
+        assert!(registry.is_face_hidden(&grass, &slab, &BlockFace::BOTTOM));
+        assert!(!registry.is_face_hidden(&grass, &slab, &BlockFace::LEFT));
+        let top_slab = Block::with_state(slab.id, BlockState::default().with_half(Half::Top));
+        assert!(!registry.is_face_hidden(&grass, &top_slab, &BlockFace::BOTTOM));
+        assert!(registry.is_face_hidden(&grass, &top_slab, &BlockFace::TOP));
+        assert!(registry
+            .get_model(registry.get_id("rose").unwrap())
+            .get_full_faces()
+            .is_empty());
+
+        // The file shipped next to the game is the embedded one
+        assert_eq!(
+            BlockRegistry::load(BlockRegistry::PATH, BlockRegistry::MODELS_PATH)
+                .unwrap()
+                .len(),
+            registry.len()
+        );
+    }
+
+    #[test]
+    fn state_decides_the_texture_of_each_side() {
+        let registry = BlockRegistry::new();
+        let log = registry.get_id("log").unwrap();
+        assert_eq!(registry.get_properties(log), &[StateProperty::Axis]);
+        assert!(registry
+            .get_properties(registry.get_id("dirt").unwrap())
+            .is_empty());
+
+        let (bark, rings) = (Vector2::new(2, 1), Vector2::new(3, 1));
+        let upright = Block::new(log);
+        assert_eq!(
+            registry.get_face_texture(&upright, &BlockFace::TOP),
+            FaceTexture {
+                pos: rings,
+                turns: 0
+            }
+        );
+        let lying = Block::with_state(log, BlockState::default().with_axis(Axis::X));
+        assert_eq!(
+            registry.get_face_texture(&lying, &BlockFace::RIGHT).pos,
+            rings
+        );
+        let side = registry.get_face_texture(&lying, &BlockFace::TOP);
+        assert_eq!(side.pos, bark);
+        assert_eq!(side.turns % 2, 1);
+    }
+
+    #[test]
+    fn fluid_shows_its_side_above_lower_fluid() {
+        let registry = BlockRegistry::new();
+        let source = Block::new(registry.get_id("water").unwrap());
+        let flowing = Block::with_state(source.id, source.state.with_level(3));
+
+        assert!(!registry.is_face_hidden(&source, &flowing, &BlockFace::LEFT));
+        assert!(registry.is_face_hidden(&flowing, &source, &BlockFace::LEFT));
+        assert!(registry.is_face_hidden(&source, &source, &BlockFace::RIGHT));
+        // Nothing shows between fluid above and below
+        assert!(registry.is_face_hidden(&source, &flowing, &BlockFace::TOP));
+        assert_eq!(registry.get_fluid(source.id).unwrap().flow_distance, 7);
+        assert!(registry
+            .get_fluid(registry.get_id("glass").unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn blocks_can_be_added_without_code() {
+        let models = BlockRegistry::DEFAULT_MODELS;
+        let registry = BlockRegistry::parse(
+            "[(id: 40, name: \"marble\", textures: (side: (3, 1), top: Some((4, 1))), hardness: 3.0)]",
+            models,
+        )
+        .unwrap();
+        let marble = registry.get(40).unwrap();
+        assert_eq!(marble.hardness, 3.0);
+        assert!(marble.solid && marble.collidable);
+        assert!(registry.is_opaque(40));
+        assert_eq!(
+            registry.get_texture_pos(40, &BlockFace::BOTTOM),
+            Vector2::new(3, 1)
+        );
+        assert_eq!(
+            registry.get_texture_pos(40, &BlockFace::TOP),
+            Vector2::new(4, 1)
+        );
+        assert!(registry.get(39).is_none());
+        assert!(registry.is_opaque(39));
+        assert_eq!(*registry.get_model(39), BlockModel::cube());
+        assert_eq!(
+            registry.get_texture_pos(39, &BlockFace::TOP),
+            BlockRegistry::MISSING_TEXTURE
+        );
+
+        let duplicate = "[(id: 1, name: \"a\", textures: (side: (0, 0))), \
+                         (id: 1, name: \"b\", textures: (side: (0, 0)))]";
+        assert!(matches!(
+            BlockRegistry::parse(duplicate, models),
+            Err(RegistryError::DuplicateId(1))
+        ));
+        assert!(matches!(
+            BlockRegistry::parse(
+                "[(id: 1, name: \"a\", textures: (side: (0, 0)), shiny: true)]",
+                models
+            ),
+            Err(RegistryError::ParseError(_))
+        ));
+        assert!(matches!(
+            BlockRegistry::parse(
+                "[(id: 1, name: \"a\", textures: (side: (0, 0)), model: \"wedge\")]",
+                models
+            ),
+            Err(RegistryError::UnknownModel(_))
+        ));
+        assert!(matches!(
+            BlockRegistry::parse(
+                "[(id: 65535, name: \"a\", textures: (side: (0, 0)))]",
+                models
+            ),
+            Err(RegistryError::InvalidId(65535))
+        ));
+
+        // New shapes are data too
+        let registry = BlockRegistry::parse(
+            "[(id: 1, name: \"a\", textures: (side: (0, 0)), model: \"pillar\")]",
+            "{\"pillar\": (elements: [Box(from: (4, 0, 4), to: (12, 16, 12))])}",
+        )
+        .unwrap();
+        assert!(registry.get_model(1).get_full_faces().is_empty());
+    }
+}
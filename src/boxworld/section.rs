@@ -0,0 +1,106 @@
+use crate::boxworld::block::Block;
+use crate::boxworld::mesher::SectionMesh;
+use crate::boxworld::palette::PalettedStorage;
+use crate::boxworld::registry::BlockRegistry;
+use crate::boxworld::visibility::SectionVisibility;
+
+// 16³ slice of a chunk column, meshed on its own
+#[derive(Clone)]
+pub struct ChunkSection {
+    blocks: PalettedStorage<Option<Block>>,
+    // Faces of the opaque and cutout blocks
+    mesh: SectionMesh,
+    // Faces of the translucent blocks, drawn after everything else
+    translucent_mesh: SectionMesh,
+    visibility: SectionVisibility,
+    is_dirty: bool,
+}
+
+impl ChunkSection {
+    pub const SIZE: usize = 16;
+    pub const TOTAL_BLOCKS: usize = Self::SIZE * Self::SIZE * Self::SIZE;
+
+    pub fn new() -> Self {
+        Self::with_blocks(PalettedStorage::new(Self::TOTAL_BLOCKS, None))
+    }
+
+    pub fn with_blocks(blocks: PalettedStorage<Option<Block>>) -> Self {
+        debug_assert_eq!(blocks.len(), Self::TOTAL_BLOCKS);
+        Self {
+            blocks,
+            mesh: SectionMesh::default(),
+            translucent_mesh: SectionMesh::default(),
+            visibility: SectionVisibility::all(),
+            is_dirty: true,
+        }
+    }
+
+    // Blocks are laid out in y, z, x order so a horizontal layer is contiguous
+    #[inline]
+    pub fn get_index(x: usize, y: usize, z: usize) -> usize {
+        (y * Self::SIZE + z) * Self::SIZE + x
+    }
+
+    #[inline]
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> Option<Block> {
+        self.blocks.get(Self::get_index(x, y, z))
+    }
+
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: Option<Block>) {
+        self.blocks.set(Self::get_index(x, y, z), block);
+        self.is_dirty = true;
+    }
+
+    pub fn get_blocks(&self) -> &PalettedStorage<Option<Block>> {
+        &self.blocks
+    }
+
+    // Only air, nothing to mesh
+    pub fn is_empty(&self) -> bool {
+        self.blocks.get_uniform() == Some(None)
+    }
+
+    // Only blocks, faces can only appear on the outer shell
+    pub fn is_solid(&self) -> bool {
+        matches!(self.blocks.get_uniform(), Some(Some(_)))
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.is_dirty = true;
+    }
+
+    // Updated along with the visibility, both only depend on the blocks of the section
+    pub fn set_mesh(
+        &mut self,
+        mesh: SectionMesh,
+        translucent_mesh: SectionMesh,
+        registry: &BlockRegistry,
+    ) {
+        self.mesh = mesh;
+        self.translucent_mesh = translucent_mesh;
+        self.visibility = SectionVisibility::from_section(self, registry);
+        self.is_dirty = false;
+    }
+
+    pub fn get_mesh(&self) -> &SectionMesh {
+        &self.mesh
+    }
+
+    pub fn get_translucent_mesh(&self) -> &SectionMesh {
+        &self.translucent_mesh
+    }
+
+    pub fn get_visibility(&self) -> &SectionVisibility {
+        &self.visibility
+    }
+}
+
+impl Default for ChunkSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
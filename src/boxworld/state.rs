@@ -0,0 +1,339 @@
+use crate::boxworld::block::BlockFace;
+use crate::boxworld::model::{FACE_ORIENTATIONS, MODEL_SIZE};
+use nalgebra::{Matrix3, Vector3};
+use serde::Deserialize;
+
+// Properties a block can be given, blocks.ron lists the ones each block uses
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateProperty {
+    Axis,
+    Facing,
+    Half,
+    Level,
+}
+
+// Axis a pillar like a log runs along
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+// Horizontal direction the front of the block looks toward, north being -z
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Facing {
+    #[default]
+    North,
+    East,
+    South,
+    West,
+}
+
+// Half of the block a slab or stairs sit in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Half {
+    #[default]
+    Bottom,
+    Top,
+}
+
+// Properties of a block on top of its id, packed in 16 bits: axis 2 bits, facing 2, half 1,
+// level 4. The default state is the way models and textures are laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockState(u16);
+
+impl BlockState {
+    pub const MAX_LEVEL: u8 = 15;
+
+    const AXIS_SHIFT: u16 = 0;
+    const FACING_SHIFT: u16 = 2;
+    const HALF_SHIFT: u16 = 4;
+    const LEVEL_SHIFT: u16 = 5;
+
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    fn get(&self, shift: u16, mask: u16) -> u16 {
+        self.0 >> shift & mask
+    }
+
+    fn with(self, shift: u16, mask: u16, value: u16) -> Self {
+        Self(self.0 & !(mask << shift) | (value & mask) << shift)
+    }
+
+    pub fn get_axis(&self) -> Axis {
+        match self.get(Self::AXIS_SHIFT, 0b11) {
+            1 => Axis::X,
+            2 => Axis::Z,
+            _ => Axis::Y,
+        }
+    }
+
+    pub fn with_axis(self, axis: Axis) -> Self {
+        let value = match axis {
+            Axis::Y => 0,
+            Axis::X => 1,
+            Axis::Z => 2,
+        };
+        self.with(Self::AXIS_SHIFT, 0b11, value)
+    }
+
+    pub fn get_facing(&self) -> Facing {
+        match self.get(Self::FACING_SHIFT, 0b11) {
+            0 => Facing::North,
+            1 => Facing::East,
+            2 => Facing::South,
+            _ => Facing::West,
+        }
+    }
+
+    pub fn with_facing(self, facing: Facing) -> Self {
+        self.with(Self::FACING_SHIFT, 0b11, facing as u16)
+    }
+
+    pub fn get_half(&self) -> Half {
+        match self.get(Self::HALF_SHIFT, 0b1) {
+            0 => Half::Bottom,
+            _ => Half::Top,
+        }
+    }
+
+    pub fn with_half(self, half: Half) -> Self {
+        self.with(Self::HALF_SHIFT, 0b1, half as u16)
+    }
+
+    pub fn get_level(&self) -> u8 {
+        self.get(Self::LEVEL_SHIFT, 0xF) as u8
+    }
+
+    pub fn with_level(self, level: u8) -> Self {
+        debug_assert!(level <= Self::MAX_LEVEL);
+        self.with(Self::LEVEL_SHIFT, 0xF, level as u16)
+    }
+
+    // State of a block placed by a player looking toward `direction`, only the given properties
+    // are set. Pillars run along the look, blocks face away from the player, and looking up puts
+    // the block in the top half, as when placing it under a ceiling.
+    pub fn from_look_direction(properties: &[StateProperty], direction: &Vector3<f32>) -> Self {
+        let mut state = Self::default();
+        let abs = direction.abs();
+        for property in properties {
+            state = match property {
+                StateProperty::Axis => state.with_axis(if abs.x >= abs.y && abs.x >= abs.z {
+                    Axis::X
+                } else if abs.z >= abs.y {
+                    Axis::Z
+                } else {
+                    Axis::Y
+                }),
+                StateProperty::Facing => state.with_facing(if abs.x > abs.z {
+                    if direction.x > 0.0 {
+                        Facing::East
+                    } else {
+                        Facing::West
+                    }
+                } else if direction.z > 0.0 {
+                    Facing::South
+                } else {
+                    Facing::North
+                }),
+                StateProperty::Half => state.with_half(if direction.y > 0.0 {
+                    Half::Top
+                } else {
+                    Half::Bottom
+                }),
+                StateProperty::Level => state,
+            };
+        }
+        state
+    }
+
+    pub fn get_rotation(&self) -> BlockRotation {
+        if *self == Self::default() {
+            return BlockRotation::identity();
+        }
+        // The half flips the model, then the axis tilts it and the facing turns it around y
+        let half = match self.get_half() {
+            Half::Bottom => Matrix3::identity(),
+            Half::Top => Matrix3::new(1, 0, 0, 0, -1, 0, 0, 0, 1),
+        };
+        let axis = match self.get_axis() {
+            Axis::X => Matrix3::new(0, 1, 0, -1, 0, 0, 0, 0, 1),
+            Axis::Y => Matrix3::identity(),
+            Axis::Z => Matrix3::new(1, 0, 0, 0, 0, -1, 0, 1, 0),
+        };
+        let facing = match self.get_facing() {
+            Facing::North => Matrix3::identity(),
+            Facing::East => Matrix3::new(0, 0, -1, 0, 1, 0, 1, 0, 0),
+            Facing::South => Matrix3::new(-1, 0, 0, 0, 1, 0, 0, 0, -1),
+            Facing::West => Matrix3::new(0, 0, 1, 0, 1, 0, -1, 0, 0),
+        };
+        BlockRotation(facing * axis * half)
+    }
+}
+
+// Maps the space of a block model, laid out in the default state, to the world for a state. Only
+// quarter turns and flips, so faces stay faces and boxes stay boxes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockRotation(Matrix3<i32>);
+
+impl BlockRotation {
+    pub fn identity() -> Self {
+        Self(Matrix3::identity())
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.0 == Matrix3::identity()
+    }
+
+    // Side of the world the side of the model ends up on
+    pub fn to_world_face(&self, face: &BlockFace) -> BlockFace {
+        if self.is_identity() {
+            return face.clone();
+        }
+        get_normal_face(&(self.0 * get_face_normal(face)))
+    }
+
+    // Side of the model ending up on the side of the world
+    pub fn to_model_face(&self, face: &BlockFace) -> BlockFace {
+        if self.is_identity() {
+            return face.clone();
+        }
+        // The inverse of a rotation is its transpose
+        get_normal_face(&(self.0.transpose() * get_face_normal(face)))
+    }
+
+    // Lowest and highest corners of a box of the model once rotated, in sixteenths of a block
+    pub fn rotate_box(&self, from: Vector3<u8>, to: Vector3<u8>) -> (Vector3<u8>, Vector3<u8>) {
+        let center = Vector3::from_element(MODEL_SIZE as i32 / 2);
+        let rotate = |corner: Vector3<u8>| self.0 * (corner.cast::<i32>() - center) + center;
+        let (from, to) = (rotate(from), rotate(to));
+        (
+            from.inf(&to).map(|coord| coord as u8),
+            from.sup(&to).map(|coord| coord as u8),
+        )
+    }
+
+    // Quarter turns of the texture of the model side shown on the side of the world, for the faces
+    // vertex.vert orients with FACE_ORIENTATIONS. A flipped texture is only turned.
+    pub fn get_texture_turns(&self, face: &BlockFace) -> u8 {
+        if self.is_identity() {
+            return 0;
+        }
+        let (model_right, _) = get_orientation(&self.to_model_face(face));
+        let (right, up) = get_orientation(face);
+        let texture_right = self.0 * model_right;
+        [right, up, -right, -up]
+            .iter()
+            .position(|direction| *direction == texture_right)
+            .unwrap() as u8
+    }
+}
+
+// Right and up directions of the face
+fn get_orientation(face: &BlockFace) -> (Vector3<i32>, Vector3<i32>) {
+    let (_, right, up) = FACE_ORIENTATIONS
+        .iter()
+        .find(|(other, _, _)| other == face)
+        .unwrap();
+    (Vector3::from(*right).cast(), Vector3::from(*up).cast())
+}
+
+fn get_face_normal(face: &BlockFace) -> Vector3<i32> {
+    let (right, up) = get_orientation(face);
+    right.cross(&up)
+}
+
+fn get_normal_face(normal: &Vector3<i32>) -> BlockFace {
+    FACE_ORIENTATIONS
+        .iter()
+        .map(|(face, _, _)| face)
+        .find(|face| get_face_normal(face) == *normal)
+        .unwrap()
+        .clone()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Axis, BlockState, Facing, Half, StateProperty};
+    use crate::boxworld::block::BlockFace;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn properties_are_packed_apart() {
+        let state = BlockState::default()
+            .with_axis(Axis::Z)
+            .with_facing(Facing::West)
+            .with_half(Half::Top)
+            .with_level(9);
+        let state = BlockState::from_bits(state.bits());
+        assert_eq!(state.get_axis(), Axis::Z);
+        assert_eq!(state.get_facing(), Facing::West);
+        assert_eq!(state.get_half(), Half::Top);
+        assert_eq!(state.get_level(), 9);
+
+        let state = state.with_facing(Facing::East).with_level(0);
+        assert_eq!(state.get_axis(), Axis::Z);
+        assert_eq!(state.get_facing(), Facing::East);
+        assert_eq!(state.get_level(), 0);
+    }
+
+    #[test]
+    fn placed_state_follows_the_look_direction() {
+        let properties = [StateProperty::Axis, StateProperty::Facing];
+        let state = BlockState::from_look_direction(&properties, &Vector3::new(0.8, -0.2, 0.3));
+        assert_eq!(state.get_axis(), Axis::X);
+        assert_eq!(state.get_facing(), Facing::East);
+        // Not listed
+        assert_eq!(state.get_half(), Half::Bottom);
+
+        let state = BlockState::from_look_direction(
+            &[StateProperty::Facing, StateProperty::Half],
+            &Vector3::new(0.1, 0.5, -0.9),
+        );
+        assert_eq!(state.get_facing(), Facing::North);
+        assert_eq!(state.get_half(), Half::Top);
+    }
+
+    #[test]
+    fn rotation_moves_faces_and_boxes() {
+        let east = BlockState::default()
+            .with_facing(Facing::East)
+            .get_rotation();
+        assert_eq!(east.to_world_face(&BlockFace::BACK), BlockFace::RIGHT);
+        assert_eq!(east.to_model_face(&BlockFace::RIGHT), BlockFace::BACK);
+        assert_eq!(east.to_world_face(&BlockFace::TOP), BlockFace::TOP);
+        // The north half of the block
+        assert_eq!(
+            east.rotate_box(Vector3::new(0, 8, 0), Vector3::new(16, 16, 8)),
+            (Vector3::new(8, 8, 0), Vector3::new(16, 16, 16))
+        );
+
+        let top = BlockState::default().with_half(Half::Top).get_rotation();
+        assert_eq!(top.to_world_face(&BlockFace::BOTTOM), BlockFace::TOP);
+        assert_eq!(
+            top.rotate_box(Vector3::new(0, 0, 0), Vector3::new(16, 8, 16)),
+            (Vector3::new(0, 8, 0), Vector3::new(16, 16, 16))
+        );
+
+        // A log along x shows its rings on the sides and its bark turned on the others
+        let log = BlockState::default().with_axis(Axis::X).get_rotation();
+        assert_eq!(log.to_model_face(&BlockFace::RIGHT), BlockFace::TOP);
+        assert_eq!(log.to_model_face(&BlockFace::LEFT), BlockFace::BOTTOM);
+        assert_eq!(log.get_texture_turns(&BlockFace::FRONT) % 2, 1);
+        assert_eq!(log.get_texture_turns(&BlockFace::TOP) % 2, 1);
+        assert_eq!(
+            BlockState::default()
+                .get_rotation()
+                .get_texture_turns(&BlockFace::FRONT),
+            0
+        );
+    }
+}
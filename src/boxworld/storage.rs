@@ -0,0 +1,308 @@
+use crate::boxworld::block::Block;
+use crate::boxworld::chunk::Chunk;
+use crate::boxworld::palette::PalettedStorage;
+use crate::boxworld::position::{ChunkPos, LocalPos};
+use crate::boxworld::section::ChunkSection;
+use crate::boxworld::state::BlockState;
+use crate::boxworld::tick::ScheduledTick;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub enum StorageError {
+    IOError,
+    ParseError,
+    InvalidChunk,
+}
+
+// Stored next to the chunks so a world is always reopened with the seed it was created with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Level {
+    pub seed: u64,
+}
+
+#[derive(Clone)]
+pub struct WorldStorage {
+    path: PathBuf,
+}
+
+impl WorldStorage {
+    pub const LEVEL_FILE: &'static str = "level.ron";
+    pub const CHUNK_DIRECTORY: &'static str = "chunks";
+    pub const CHUNK_FORMAT_VERSION: u8 = 5;
+    const TEMP_EXTENSION: &'static str = "chunk.tmp";
+
+    // Creates the world directory when it does not exist yet
+    pub fn open<P: AsRef<Path>>(path: P, seed: u64) -> Result<(Self, Level), StorageError> {
+        let path = path.as_ref().to_path_buf();
+        std::fs::create_dir_all(path.join(Self::CHUNK_DIRECTORY))
+            .map_err(|_| StorageError::IOError)?;
+
+        let level_path = path.join(Self::LEVEL_FILE);
+        let level = if level_path.exists() {
+            let content =
+                std::fs::read_to_string(&level_path).map_err(|_| StorageError::IOError)?;
+            ron::from_str(&content).map_err(|_| StorageError::ParseError)?
+        } else {
+            let level = Level { seed };
+            let content = ron::to_string(&level).map_err(|_| StorageError::ParseError)?;
+            std::fs::write(&level_path, content).map_err(|_| StorageError::IOError)?;
+            level
+        };
+
+        Ok((Self { path }, level))
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    fn get_chunk_path(&self, chunk_coord: &ChunkPos) -> PathBuf {
+        self.path
+            .join(Self::CHUNK_DIRECTORY)
+            .join(format!("{}_{}.chunk", chunk_coord.x, chunk_coord.z))
+    }
+
+    // The scheduled ticks are stored as delays, `tick` is the current game tick
+    pub fn load_chunk(
+        &self,
+        chunk_coord: ChunkPos,
+        tick: u64,
+    ) -> Result<Option<Chunk>, StorageError> {
+        let path = self.get_chunk_path(&chunk_coord);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = std::fs::read(path).map_err(|_| StorageError::IOError)?;
+        chunk_from_bytes(&bytes, chunk_coord, tick).map(Some)
+    }
+
+    // Written next to the chunk file then moved over it, so it is never left half written
+    pub fn save_chunk(&self, chunk: &Chunk, tick: u64) -> Result<(), StorageError> {
+        let path = self.get_chunk_path(chunk.get_chunk_coord());
+        let temp_path = path.with_extension(Self::TEMP_EXTENSION);
+        std::fs::write(&temp_path, chunk_to_bytes(chunk, tick))
+            .and_then(|_| std::fs::rename(&temp_path, &path))
+            .map_err(|_| StorageError::IOError)
+    }
+}
+
+// Format version, then for every section from the bottom: the palette as pairs of little endian
+// u16, 0 for air and the block id + 1 otherwise then the bits of the block state, followed by the
+// bit-packed palette indices as little endian words. Then the count of scheduled ticks as a
+// little endian u32, and for each one the ticks left from `tick` as a u32, x, y and z as bytes and
+// the block id as a u16.
+fn chunk_to_bytes(chunk: &Chunk, tick: u64) -> Vec<u8> {
+    let mut bytes = vec![WorldStorage::CHUNK_FORMAT_VERSION];
+    for section in chunk.get_sections() {
+        let mut blocks = section.get_blocks().clone();
+        blocks.compact();
+
+        let palette = blocks.get_palette();
+        bytes.push(blocks.get_bits_per_index() as u8);
+        bytes.extend((palette.len() as u16).to_le_bytes());
+        for maybe_block in palette {
+            let (id, state) = match maybe_block {
+                Some(block) => (block.id + 1, block.state.bits()),
+                None => (0, 0),
+            };
+            bytes.extend(id.to_le_bytes());
+            bytes.extend(state.to_le_bytes());
+        }
+        for word in blocks.get_data() {
+            bytes.extend(word.to_le_bytes());
+        }
+    }
+
+    let scheduled_ticks = chunk.get_scheduled_ticks();
+    bytes.extend((scheduled_ticks.len() as u32).to_le_bytes());
+    for scheduled in scheduled_ticks {
+        let delay = scheduled.tick.saturating_sub(tick).min(u32::MAX as u64) as u32;
+        bytes.extend(delay.to_le_bytes());
+        bytes.extend([scheduled.pos.x, scheduled.pos.y, scheduled.pos.z].map(|coord| coord as u8));
+        bytes.extend(scheduled.id.to_le_bytes());
+    }
+    bytes
+}
+
+fn chunk_from_bytes(bytes: &[u8], chunk_coord: ChunkPos, tick: u64) -> Result<Chunk, StorageError> {
+    let (version, mut rest) = bytes.split_first().ok_or(StorageError::InvalidChunk)?;
+    if *version != WorldStorage::CHUNK_FORMAT_VERSION {
+        return Err(StorageError::InvalidChunk);
+    }
+
+    let mut sections = Vec::with_capacity(Chunk::SECTION_COUNT);
+    for _ in 0..Chunk::SECTION_COUNT {
+        let (header, palette_rest) = rest.split_at_checked(3).ok_or(StorageError::InvalidChunk)?;
+        let bits_per_index = header[0] as u32;
+        // Checked again by from_raw, but the word count cannot be computed past it
+        if bits_per_index > u16::BITS {
+            return Err(StorageError::InvalidChunk);
+        }
+        let palette_len = u16::from_le_bytes([header[1], header[2]]) as usize;
+
+        let (palette_bytes, data_rest) = palette_rest
+            .split_at_checked(palette_len * 4)
+            .ok_or(StorageError::InvalidChunk)?;
+        let palette = palette_bytes
+            .chunks_exact(4)
+            .map(|entry| match u16::from_le_bytes([entry[0], entry[1]]) {
+                0 => None,
+                id => Some(Block::with_state(
+                    id - 1,
+                    BlockState::from_bits(u16::from_le_bytes([entry[2], entry[3]])),
+                )),
+            })
+            .collect::<Vec<Option<Block>>>();
+
+        let word_count = PalettedStorage::<Option<Block>>::get_word_count(
+            ChunkSection::TOTAL_BLOCKS,
+            bits_per_index,
+        );
+        let (data_bytes, section_rest) = data_rest
+            .split_at_checked(word_count * 8)
+            .ok_or(StorageError::InvalidChunk)?;
+        let data = data_bytes
+            .chunks_exact(8)
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        rest = section_rest;
+
+        let blocks =
+            PalettedStorage::from_raw(ChunkSection::TOTAL_BLOCKS, palette, bits_per_index, data)
+                .ok_or(StorageError::InvalidChunk)?;
+        sections.push(ChunkSection::with_blocks(blocks));
+    }
+    let mut chunk = Chunk::with_sections(sections, chunk_coord);
+
+    let (count, scheduled_bytes) = rest.split_at_checked(4).ok_or(StorageError::InvalidChunk)?;
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+    if scheduled_bytes.len() != count * 9 {
+        return Err(StorageError::InvalidChunk);
+    }
+    for entry in scheduled_bytes.chunks_exact(9) {
+        let delay = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+        let (x, y, z) = (entry[4] as usize, entry[5] as usize, entry[6] as usize);
+        if x >= Chunk::CHUNK_SIDE_BLOCK
+            || y >= Chunk::CHUNK_VERTICAL_BLOCK
+            || z >= Chunk::CHUNK_SIDE_BLOCK
+        {
+            return Err(StorageError::InvalidChunk);
+        }
+        chunk.schedule_tick(ScheduledTick {
+            tick: tick + delay as u64,
+            pos: LocalPos::new(x, y, z),
+            id: u16::from_le_bytes([entry[7], entry[8]]),
+        });
+    }
+
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{chunk_from_bytes, chunk_to_bytes, StorageError, WorldStorage};
+    use crate::boxworld::block::Block;
+    use crate::boxworld::chunk::Chunk;
+    use crate::boxworld::generator::WorldGenerator;
+    use crate::boxworld::position::{ChunkPos, LocalPos};
+    use crate::boxworld::registry::BlockRegistry;
+    use crate::boxworld::state::{Axis, BlockState};
+    use crate::boxworld::tick::ScheduledTick;
+
+    #[test]
+    fn saved_chunk_is_loaded_back() {
+        let path = std::env::temp_dir().join("boxcraft-storage-test");
+        let _ = std::fs::remove_dir_all(&path);
+
+        let (storage, level) = WorldStorage::open(&path, 7).unwrap();
+        assert_eq!(level.seed, 7);
+
+        let registry = BlockRegistry::new();
+        let mut chunk = WorldGenerator::new(level.seed, &registry)
+            .unwrap()
+            .generate(ChunkPos::new(-2, 3));
+        // A log lying along z
+        let log = Block::with_state(
+            registry.get_id("log").unwrap(),
+            BlockState::default().with_axis(Axis::Z),
+        );
+        chunk.set_block(LocalPos::new(1, 100, 2), Some(log));
+        assert!(storage
+            .load_chunk(ChunkPos::new(-2, 3), 0)
+            .unwrap()
+            .is_none());
+        storage.save_chunk(&chunk, 0).unwrap();
+
+        // The stored seed wins over the one given when reopening
+        let (storage, level) = WorldStorage::open(&path, 8).unwrap();
+        assert_eq!(level.seed, 7);
+        let loaded = storage
+            .load_chunk(ChunkPos::new(-2, 3), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk_to_bytes(&loaded, 0), chunk_to_bytes(&chunk, 0));
+        assert_eq!(loaded.get_block(LocalPos::new(1, 100, 2)), Some(log));
+        assert!(chunk_to_bytes(&chunk, 0).len() < Chunk::MAXIMUM_TOTAL_BLOCKS / 4);
+        // Nothing left besides the chunk file
+        let chunk_files = std::fs::read_dir(path.join(WorldStorage::CHUNK_DIRECTORY))
+            .unwrap()
+            .count();
+        assert_eq!(chunk_files, 1);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn scheduled_ticks_keep_their_delay() {
+        let path = std::env::temp_dir().join("boxcraft-scheduled-ticks-test");
+        let _ = std::fs::remove_dir_all(&path);
+        let (storage, _) = WorldStorage::open(&path, 0).unwrap();
+
+        let mut chunk = Chunk::new(ChunkPos::new(1, -1));
+        let sand = BlockRegistry::new().get_id("sand").unwrap();
+        let scheduled = |tick, pos| ScheduledTick {
+            tick,
+            pos,
+            id: sand,
+        };
+        chunk.schedule_tick(scheduled(130, LocalPos::new(15, 127, 3)));
+        chunk.schedule_tick(scheduled(101, LocalPos::new(0, 5, 0)));
+        // Overdue, run on the first tick after loading
+        chunk.schedule_tick(scheduled(90, LocalPos::new(2, 5, 0)));
+        storage.save_chunk(&chunk, 100).unwrap();
+
+        // Read back in a world whose clock started over
+        let loaded = storage
+            .load_chunk(ChunkPos::new(1, -1), 10)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            loaded.get_scheduled_ticks(),
+            [
+                scheduled(10, LocalPos::new(2, 5, 0)),
+                scheduled(11, LocalPos::new(0, 5, 0)),
+                scheduled(40, LocalPos::new(15, 127, 3)),
+            ]
+        );
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_chunk_is_rejected() {
+        let mut bytes = chunk_to_bytes(&Chunk::new(ChunkPos::new(0, 0)), 0);
+        assert!(chunk_from_bytes(&bytes, ChunkPos::new(0, 0), 0).is_ok());
+
+        // Too many bits per index for the first section
+        bytes[1] = 200;
+        assert_eq!(
+            chunk_from_bytes(&bytes, ChunkPos::new(0, 0), 0).err(),
+            Some(StorageError::InvalidChunk)
+        );
+        assert_eq!(
+            chunk_from_bytes(&bytes[..40], ChunkPos::new(0, 0), 0).err(),
+            Some(StorageError::InvalidChunk)
+        );
+    }
+}
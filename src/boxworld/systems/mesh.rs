@@ -0,0 +1,7 @@
+use crate::boxworld::registry::BlockRegistry;
+use crate::boxworld::BoxWorld;
+use bevy_ecs::prelude::*;
+
+pub fn update_meshes(mut box_world: ResMut<BoxWorld>, registry: Res<BlockRegistry>) {
+    box_world.update_meshes(&registry);
+}
@@ -1,2 +1,4 @@
+pub mod mesh;
 pub mod sync_camera;
+pub mod tick;
 pub mod worker;
@@ -0,0 +1,12 @@
+use crate::boxworld::registry::BlockRegistry;
+use crate::boxworld::tick::BlockTicks;
+use crate::boxworld::BoxWorld;
+use bevy_ecs::prelude::*;
+
+pub fn tick_blocks(
+    mut box_world: ResMut<BoxWorld>,
+    registry: Res<BlockRegistry>,
+    mut block_ticks: ResMut<BlockTicks>,
+) {
+    block_ticks.run(&mut box_world, &registry);
+}
@@ -1,14 +1,10 @@
-
 use crate::boxworld::worker::BoxWorldTask;
 use crate::boxworld::BoxWorld;
 use crate::game::camera::Camera;
-use crate::renderer::game_renderer::GameRenderer;
-use crate::renderer::Renderer;
 use crate::worker::ComputeTaskPool;
 use bevy_ecs::prelude::*;
 use futures_lite::future;
 
-
 pub fn update_worker(
     mut commands: Commands,
     mut query: Query<(Entity, &mut BoxWorldTask)>,
@@ -17,27 +13,23 @@ pub fn update_worker(
     for (entity, mut task) in query.iter_mut() {
         let maybe_result = future::block_on(future::poll_once(&mut task.0));
         if let Some(result) = maybe_result {
-            box_world.insert_chunk(result.coord, result.chunk);
+            // The load may have been cancelled after the chunk was ready
+            if box_world.is_loading(&result.coord) {
+                box_world.insert_chunk(result.coord, result.chunk);
+            }
             commands.entity(entity).despawn();
         }
     }
+    box_world.spawn_loads(&mut commands);
 }
 
-pub fn calculate(
-    commands: Commands,
-    mut world: ResMut<BoxWorld>,
-    renderer: Res<Renderer>,
-    camera: Res<Camera>,
-    mut game_renderer: ResMut<GameRenderer>,
-) {
-    if world.update_current_chunk_coord(&camera) {
+pub fn calculate(mut commands: Commands, mut world: ResMut<BoxWorld>, camera: Res<Camera>) {
+    let range_changed = world.take_range_changed();
+    if world.update_current_chunk_coord(&camera) || range_changed {
         // Enqueue calculation
-        println!("Enqueued");
-        world.enqueue_work(commands);
-    }
-
-    if world.is_dirty() {
-        println!("Dirty!");
-        world.update_blocks(&renderer, &mut game_renderer);
+        world.cancel_far_loads(&mut commands);
+        world.update_load_queue(&camera.get_direction_horizontally().xz());
+        world.remove_far_chunks();
+        world.update_lods();
     }
 }
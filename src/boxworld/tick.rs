@@ -0,0 +1,294 @@
+use crate::boxworld::block::Block;
+use crate::boxworld::fluid;
+use crate::boxworld::position::{BlockPos, LocalPos};
+use crate::boxworld::registry::{BlockId, BlockRegistry};
+use crate::boxworld::section::ChunkSection;
+use crate::boxworld::BoxWorld;
+use bevy_ecs::prelude::Resource;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+// Update a block asked for at a game tick, queued in its chunk and saved with it
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ScheduledTick {
+    pub tick: u64,
+    pub pos: LocalPos,
+    // The update is dropped when another block took the place of this one in the meantime
+    pub id: BlockId,
+}
+
+// What a tick handler can look at and change
+pub struct TickContext<'a> {
+    pub world: &'a mut BoxWorld,
+    pub registry: &'a BlockRegistry,
+    pub rng: &'a mut StdRng,
+}
+
+pub type TickHandler = fn(&mut TickContext, BlockPos, Block);
+
+// Handlers the block types registered, for random ticks run on blocks picked at random in every
+// loaded section each tick, and for scheduled ticks run at the tick they were asked for
+#[derive(Resource)]
+pub struct BlockTicks {
+    random_handlers: HashMap<BlockId, TickHandler>,
+    // With the delay in ticks after the block or one next to it changed
+    scheduled_handlers: HashMap<BlockId, (u64, TickHandler)>,
+    random_ticks_per_section: usize,
+    rng: StdRng,
+}
+
+impl BlockTicks {
+    // The block itself, then the blocks sharing a side with it
+    const UPDATE_OFFSETS: [(i32, i32, i32); 7] = [
+        (0, 0, 0),
+        (-1, 0, 0),
+        (1, 0, 0),
+        (0, -1, 0),
+        (0, 1, 0),
+        (0, 0, -1),
+        (0, 0, 1),
+    ];
+
+    pub fn new(random_ticks_per_section: usize, seed: u64) -> Self {
+        Self {
+            random_handlers: HashMap::new(),
+            scheduled_handlers: HashMap::new(),
+            random_ticks_per_section,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    // With the handlers of the blocks in the registry that have some
+    pub fn with_block_handlers(
+        random_ticks_per_section: usize,
+        seed: u64,
+        registry: &BlockRegistry,
+    ) -> Self {
+        let mut ticks = Self::new(random_ticks_per_section, seed);
+        if let Some(dirt) = registry.get_id("dirt") {
+            ticks.register_random(dirt, spread_grass);
+        }
+        if let Some(sand) = registry.get_id("sand") {
+            ticks.register_scheduled(sand, 2, fall);
+        }
+        for (id, definition) in registry.get_fluids() {
+            ticks.register_scheduled(id, definition.flow_delay, fluid::flow);
+        }
+        ticks
+    }
+
+    pub fn register_random(&mut self, id: BlockId, handler: TickHandler) {
+        self.random_handlers.insert(id, handler);
+    }
+
+    pub fn register_scheduled(&mut self, id: BlockId, delay: u64, handler: TickHandler) {
+        self.scheduled_handlers.insert(id, (delay, handler));
+    }
+
+    // Moves the world on by a game tick
+    pub fn run(&mut self, world: &mut BoxWorld, registry: &BlockRegistry) {
+        let tick = world.advance_tick();
+
+        // Blocks changed since the last tick wake up the ones around them
+        for changed in world.take_changed_blocks() {
+            for (x, y, z) in Self::UPDATE_OFFSETS {
+                let pos = changed.offset(x, y, z);
+                let Some(block) = world.get_block(pos) else {
+                    continue;
+                };
+                if let Some((delay, _)) = self.scheduled_handlers.get(&block.id) {
+                    world.schedule_tick(pos, block.id, *delay);
+                }
+            }
+        }
+
+        // Picked before any handler runs, so blocks they change are not picked in the same tick
+        let mut chunks = world.get_chunks().collect::<Vec<_>>();
+        chunks.sort_by_key(|(chunk_coord, _)| (chunk_coord.x, chunk_coord.z));
+        let mut random_positions = Vec::new();
+        for (chunk_coord, chunk) in chunks {
+            for (section_y, section) in chunk.get_sections().iter().enumerate() {
+                if section.is_empty() {
+                    continue;
+                }
+                for _ in 0..self.random_ticks_per_section {
+                    let local = LocalPos::new(
+                        self.rng.gen_range(0..ChunkSection::SIZE),
+                        section_y * ChunkSection::SIZE + self.rng.gen_range(0..ChunkSection::SIZE),
+                        self.rng.gen_range(0..ChunkSection::SIZE),
+                    );
+                    random_positions.push(BlockPos::from_local(chunk_coord, local));
+                }
+            }
+        }
+        for pos in random_positions {
+            let Some(block) = world.get_block(pos) else {
+                continue;
+            };
+            if let Some(handler) = self.random_handlers.get(&block.id).copied() {
+                let mut context = TickContext {
+                    world,
+                    registry,
+                    rng: &mut self.rng,
+                };
+                handler(&mut context, pos, block);
+            }
+        }
+
+        for (pos, id) in world.take_due_ticks(tick) {
+            let Some(block) = world.get_block(pos).filter(|block| block.id == id) else {
+                continue;
+            };
+            if let Some((_, handler)) = self.scheduled_handlers.get(&id).copied() {
+                let mut context = TickContext {
+                    world,
+                    registry,
+                    rng: &mut self.rng,
+                };
+                handler(&mut context, pos, block);
+            }
+        }
+    }
+}
+
+// Dirt with nothing opaque on top turns to grass when grass grows around it
+fn spread_grass(context: &mut TickContext, pos: BlockPos, _block: Block) {
+    let Some(grass) = context.registry.get_id("grass") else {
+        return;
+    };
+    let world = &context.world;
+    if let Some(above) = world.get_block(pos.offset(0, 1, 0)) {
+        if context.registry.is_opaque(above.id) {
+            return;
+        }
+    }
+    let is_next_to_grass = (-1..=1).any(|x| {
+        (-1..=1).any(|y| {
+            (-1..=1).any(|z| {
+                world
+                    .get_block(pos.offset(x, y, z))
+                    .is_some_and(|block| block.id == grass)
+            })
+        })
+    });
+    if is_next_to_grass {
+        context.world.set_block(pos, grass);
+    }
+}
+
+// Drops the block by one while there is air below, landing wakes it up again
+fn fall(context: &mut TickContext, pos: BlockPos, block: Block) {
+    let below = pos.offset(0, -1, 0);
+    if context.world.get_block(below).is_none() && context.world.replace_block(below, Some(block)) {
+        context.world.remove_block(pos);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{BlockTicks, ScheduledTick};
+    use crate::boxworld::chunk::Chunk;
+    use crate::boxworld::position::{BlockPos, ChunkPos, LocalPos};
+    use crate::boxworld::registry::BlockRegistry;
+    use crate::boxworld::BoxWorld;
+    use crate::game::config::Config;
+
+    // An empty chunk at the origin, the ticks seeded with 0
+    pub(crate) fn world_with_chunk(
+        registry: &BlockRegistry,
+        random_ticks_per_section: usize,
+    ) -> (BoxWorld, BlockTicks) {
+        let mut world = BoxWorld::new(&Config::default(), registry).unwrap();
+        world.insert_chunk(ChunkPos::new(0, 0), Chunk::new(ChunkPos::new(0, 0)));
+        let ticks = BlockTicks::with_block_handlers(random_ticks_per_section, 0, registry);
+        (world, ticks)
+    }
+
+    #[test]
+    fn scheduled_ticks_come_out_in_order() {
+        let registry = BlockRegistry::new();
+        let sand = registry.get_id("sand").unwrap();
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0));
+        let scheduled = |tick, x| ScheduledTick {
+            tick,
+            pos: LocalPos::new(x, 0, 0),
+            id: sand,
+        };
+        chunk.schedule_tick(scheduled(7, 0));
+        chunk.schedule_tick(scheduled(3, 1));
+        chunk.schedule_tick(scheduled(5, 2));
+        // The block already waits for an update
+        chunk.schedule_tick(scheduled(4, 1));
+
+        assert_eq!(chunk.take_due_ticks(2), []);
+        assert_eq!(chunk.take_due_ticks(5), [scheduled(3, 1), scheduled(5, 2)]);
+        assert_eq!(chunk.get_scheduled_ticks(), [scheduled(7, 0)]);
+        // Waits for an update again once the last one came out
+        chunk.schedule_tick(scheduled(9, 1));
+        assert_eq!(chunk.take_due_ticks(9), [scheduled(7, 0), scheduled(9, 1)]);
+    }
+
+    #[test]
+    fn sand_falls_until_it_lands() {
+        let registry = BlockRegistry::new();
+        let stone = registry.get_id("stone").unwrap();
+        let sand = registry.get_id("sand").unwrap();
+        let (mut world, mut ticks) = world_with_chunk(&registry, 0);
+        world.set_block(BlockPos::new(2, 0, 3), stone);
+        world.set_block(BlockPos::new(2, 4, 3), sand);
+
+        // Every block down takes the tick the change is seen on and the delay of two ticks
+        for _ in 0..8 {
+            ticks.run(&mut world, &registry);
+        }
+        assert!(world.get_block(BlockPos::new(2, 1, 3)).is_none());
+        ticks.run(&mut world, &registry);
+        assert_eq!(world.get_block(BlockPos::new(2, 1, 3)).unwrap().id, sand);
+        assert!(world.get_block(BlockPos::new(2, 4, 3)).is_none());
+
+        for _ in 0..10 {
+            ticks.run(&mut world, &registry);
+        }
+        assert_eq!(world.get_block(BlockPos::new(2, 1, 3)).unwrap().id, sand);
+        assert_eq!(world.get_block(BlockPos::new(2, 0, 3)).unwrap().id, stone);
+    }
+
+    #[test]
+    fn scheduled_tick_of_a_replaced_block_is_dropped() {
+        let registry = BlockRegistry::new();
+        let stone = registry.get_id("stone").unwrap();
+        let sand = registry.get_id("sand").unwrap();
+        let (mut world, mut ticks) = world_with_chunk(&registry, 0);
+        world.set_block(BlockPos::new(2, 4, 3), sand);
+        ticks.run(&mut world, &registry);
+
+        world.set_block(BlockPos::new(2, 4, 3), stone);
+        for _ in 0..4 {
+            ticks.run(&mut world, &registry);
+        }
+        assert_eq!(world.get_block(BlockPos::new(2, 4, 3)).unwrap().id, stone);
+        assert!(world.get_block(BlockPos::new(2, 3, 3)).is_none());
+    }
+
+    #[test]
+    fn random_ticks_spread_grass_to_uncovered_dirt() {
+        let registry = BlockRegistry::new();
+        let grass = registry.get_id("grass").unwrap();
+        let dirt = registry.get_id("dirt").unwrap();
+        let stone = registry.get_id("stone").unwrap();
+        // Enough picks for every block of the section to be ticked
+        let (mut world, mut ticks) = world_with_chunk(&registry, 50_000);
+        world.set_block(BlockPos::new(4, 0, 4), grass);
+        world.set_block(BlockPos::new(5, 0, 4), dirt);
+        world.set_block(BlockPos::new(4, 0, 5), dirt);
+        world.set_block(BlockPos::new(4, 1, 5), stone);
+        // Too far from the grass
+        world.set_block(BlockPos::new(7, 0, 4), dirt);
+
+        ticks.run(&mut world, &registry);
+        assert_eq!(world.get_block(BlockPos::new(5, 0, 4)).unwrap().id, grass);
+        assert_eq!(world.get_block(BlockPos::new(4, 0, 5)).unwrap().id, dirt);
+        assert_eq!(world.get_block(BlockPos::new(7, 0, 4)).unwrap().id, dirt);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::boxworld::block::{Block, BlockFace};
+use crate::boxworld::chunk::Chunk;
+use crate::boxworld::position::BlockPos;
+use crate::boxworld::registry::BlockRegistry;
+use crate::boxworld::section::ChunkSection;
+use nalgebra::{Point3, Vector3};
+use std::collections::{HashSet, VecDeque};
+
+// Section coordinate: the chunk coordinate on x and z, the index of the section in its chunk on y
+pub type SectionPos = Vector3<i32>;
+
+// Lowest and highest corners of the section in the world
+pub fn get_section_bounds(pos: &SectionPos) -> (Point3<f32>, Point3<f32>) {
+    let side = Chunk::CHUNK_SIDE_BLOCK as i32;
+    let size = ChunkSection::SIZE as i32;
+    let min = Point3::new(pos.x * side, BlockPos::MIN_Y + pos.y * size, pos.z * side).cast::<f32>()
+        * Block::SIZE;
+    (
+        min,
+        min + Vector3::from_element(ChunkSection::SIZE as f32 * Block::SIZE),
+    )
+}
+
+const FACES: [BlockFace; 6] = [
+    BlockFace::FRONT,
+    BlockFace::BACK,
+    BlockFace::RIGHT,
+    BlockFace::LEFT,
+    BlockFace::TOP,
+    BlockFace::BOTTOM,
+];
+
+#[inline]
+fn get_face_index(face: &BlockFace) -> usize {
+    face.bits().trailing_zeros() as usize
+}
+
+fn get_opposite(face: &BlockFace) -> BlockFace {
+    FACES[get_face_index(face) ^ 1].clone()
+}
+
+fn get_offset(face: &BlockFace) -> Vector3<i32> {
+    match get_face_index(face) {
+        0 => Vector3::new(0, 0, 1),
+        1 => Vector3::new(0, 0, -1),
+        2 => Vector3::new(1, 0, 0),
+        3 => Vector3::new(-1, 0, 0),
+        4 => Vector3::new(0, 1, 0),
+        _ => Vector3::new(0, -1, 0),
+    }
+}
+
+// For every pair of faces of a section, whether air or blocks that can be seen through connect
+// them through the section
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct SectionVisibility {
+    connections: u64,
+}
+
+impl SectionVisibility {
+    pub fn all() -> Self {
+        Self {
+            connections: (1 << 36) - 1,
+        }
+    }
+
+    // Flood fills every pocket of air and non opaque blocks, connecting all the faces the pocket
+    // touches
+    pub fn from_section(section: &ChunkSection, registry: &BlockRegistry) -> Self {
+        if section.is_empty() {
+            return Self::all();
+        }
+
+        let size = ChunkSection::SIZE;
+        let last = size - 1;
+        let is_open = |x: usize, y: usize, z: usize| {
+            section
+                .get_block(x, y, z)
+                .is_none_or(|block| !registry.is_opaque(block.id))
+        };
+        if section.is_solid() {
+            return if is_open(0, 0, 0) {
+                Self::all()
+            } else {
+                Self::default()
+            };
+        }
+
+        let mut visibility = Self::default();
+        let mut visited = vec![false; ChunkSection::TOTAL_BLOCKS];
+        let mut stack = Vec::new();
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    if visited[ChunkSection::get_index(x, y, z)] || !is_open(x, y, z) {
+                        continue;
+                    }
+
+                    let mut faces = BlockFace::empty();
+                    visited[ChunkSection::get_index(x, y, z)] = true;
+                    stack.push((x, y, z));
+                    while let Some((x, y, z)) = stack.pop() {
+                        let borders = [
+                            (x == 0, BlockFace::LEFT),
+                            (x == last, BlockFace::RIGHT),
+                            (y == 0, BlockFace::BOTTOM),
+                            (y == last, BlockFace::TOP),
+                            (z == 0, BlockFace::BACK),
+                            (z == last, BlockFace::FRONT),
+                        ];
+                        for (is_on_border, face) in borders {
+                            if is_on_border {
+                                faces |= face;
+                            }
+                        }
+
+                        let neighbours = [
+                            (x > 0).then(|| (x - 1, y, z)),
+                            (x < last).then(|| (x + 1, y, z)),
+                            (y > 0).then(|| (x, y - 1, z)),
+                            (y < last).then(|| (x, y + 1, z)),
+                            (z > 0).then(|| (x, y, z - 1)),
+                            (z < last).then(|| (x, y, z + 1)),
+                        ];
+                        for (x, y, z) in neighbours.into_iter().flatten() {
+                            let index = ChunkSection::get_index(x, y, z);
+                            if !visited[index] && is_open(x, y, z) {
+                                visited[index] = true;
+                                stack.push((x, y, z));
+                            }
+                        }
+                    }
+                    visibility.connect(faces);
+                }
+            }
+        }
+        visibility
+    }
+
+    fn connect(&mut self, faces: BlockFace) {
+        for from in faces.iter() {
+            for to in faces.iter() {
+                self.connections |= 1 << (get_face_index(&from) * 6 + get_face_index(&to));
+            }
+        }
+    }
+
+    pub fn is_connected(&self, from: &BlockFace, to: &BlockFace) -> bool {
+        self.connections & 1 << (get_face_index(from) * 6 + get_face_index(to)) != 0
+    }
+}
+
+// Breadth-first walk from the section of the camera to every section that can be seen through
+// air. A section is entered by a face and left by a face connected to it, never going back
+// toward the camera, and only while the section is in view. `get_visibility` gives none for the
+// sections that are not loaded.
+pub fn find_visible_sections(
+    start: SectionPos,
+    get_visibility: impl Fn(&SectionPos) -> Option<SectionVisibility>,
+    is_in_view: impl Fn(&SectionPos) -> bool,
+) -> HashSet<SectionPos> {
+    let mut visible_sections = HashSet::new();
+    let Some(start_visibility) = get_visibility(&start) else {
+        return visible_sections;
+    };
+    visible_sections.insert(start);
+
+    let mut queue = VecDeque::new();
+    queue.push_back((start, start_visibility, None, BlockFace::empty()));
+    while let Some((pos, visibility, entered_by, directions)) = queue.pop_front() {
+        for face in FACES.iter() {
+            if directions.contains(get_opposite(face)) {
+                continue;
+            }
+            if let Some(entered_by) = &entered_by {
+                if !visibility.is_connected(entered_by, face) {
+                    continue;
+                }
+            }
+
+            let next = pos + get_offset(face);
+            if visible_sections.contains(&next) || !is_in_view(&next) {
+                continue;
+            }
+            if let Some(next_visibility) = get_visibility(&next) {
+                visible_sections.insert(next);
+                queue.push_back((
+                    next,
+                    next_visibility,
+                    Some(get_opposite(face)),
+                    directions.clone() | face.clone(),
+                ));
+            }
+        }
+    }
+    visible_sections
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_visible_sections, SectionPos, SectionVisibility};
+    use crate::boxworld::block::{Block, BlockFace};
+    use crate::boxworld::registry::{BlockId, BlockRegistry};
+    use crate::boxworld::section::ChunkSection;
+    use nalgebra::Vector3;
+
+    fn section_with_layer(layer_y: usize, id: BlockId) -> ChunkSection {
+        let mut section = ChunkSection::new();
+        for x in 0..ChunkSection::SIZE {
+            for z in 0..ChunkSection::SIZE {
+                section.set_block(x, layer_y, z, Some(Block::new(id)));
+            }
+        }
+        section
+    }
+
+    #[test]
+    fn layer_splits_the_section() {
+        let registry = BlockRegistry::new();
+        let dirt = registry.get_id("dirt").unwrap();
+        let visibility = SectionVisibility::from_section(&section_with_layer(8, dirt), &registry);
+        assert!(!visibility.is_connected(&BlockFace::TOP, &BlockFace::BOTTOM));
+        assert!(visibility.is_connected(&BlockFace::LEFT, &BlockFace::RIGHT));
+        assert!(visibility.is_connected(&BlockFace::TOP, &BlockFace::FRONT));
+        assert!(visibility.is_connected(&BlockFace::BOTTOM, &BlockFace::BACK));
+
+        // With the layer at the bottom, no air touches the bottom face
+        let visibility = SectionVisibility::from_section(&section_with_layer(0, dirt), &registry);
+        assert!(!visibility.is_connected(&BlockFace::TOP, &BlockFace::BOTTOM));
+        assert!(visibility.is_connected(&BlockFace::TOP, &BlockFace::LEFT));
+        assert!(!visibility.is_connected(&BlockFace::BOTTOM, &BlockFace::LEFT));
+
+        assert_eq!(
+            SectionVisibility::from_section(&ChunkSection::new(), &registry),
+            SectionVisibility::all()
+        );
+
+        // Glass can be seen through
+        let glass = registry.get_id("glass").unwrap();
+        let visibility = SectionVisibility::from_section(&section_with_layer(8, glass), &registry);
+        assert!(visibility.is_connected(&BlockFace::TOP, &BlockFace::BOTTOM));
+    }
+
+    #[test]
+    fn sections_behind_a_wall_are_not_visible() {
+        // A single column of 4 sections whose second one is split by a floor
+        let registry = BlockRegistry::new();
+        let dirt = registry.get_id("dirt").unwrap();
+        let floor = SectionVisibility::from_section(&section_with_layer(8, dirt), &registry);
+        let get_visibility = |pos: &SectionPos| match (pos.x, pos.y, pos.z) {
+            (0, 1, 0) => Some(floor),
+            (0, 0..=3, 0) => Some(SectionVisibility::all()),
+            _ => None,
+        };
+
+        let from_bottom = find_visible_sections(Vector3::new(0, 0, 0), get_visibility, |_| true);
+        assert_eq!(from_bottom.len(), 2);
+        assert!(from_bottom.contains(&Vector3::new(0, 1, 0)));
+
+        let from_top = find_visible_sections(Vector3::new(0, 3, 0), get_visibility, |_| true);
+        assert_eq!(from_top.len(), 3);
+
+        // Out of view sections stop the walk
+        let in_view = find_visible_sections(Vector3::new(0, 3, 0), get_visibility, |pos| pos.y > 1);
+        assert_eq!(in_view.len(), 2);
+    }
+}
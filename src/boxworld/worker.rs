@@ -1,12 +1,12 @@
 use crate::boxworld::chunk::Chunk;
+use crate::boxworld::position::ChunkPos;
 use bevy_ecs::prelude::*;
 use bevy_tasks::Task;
-use nalgebra::Vector2;
 
 use std::ops::Deref;
 
 pub struct BoxWorldTaskResult {
-    pub coord: Vector2<i32>,
+    pub coord: ChunkPos,
     pub chunk: Chunk,
 }
 
@@ -1,8 +1,12 @@
+use crate::app::error::AppError;
+use crate::app::input::plugin::InputPlugin;
 use crate::app::input::InputManager;
 use crate::boxworld::chunk::Chunk;
+use crate::boxworld::position::ChunkPos;
+use crate::game::config::Config;
 use crate::game::schedule::ScheduleStage;
 use crate::game::systems::Time;
-use crate::plugin::Plugin;
+use crate::plugin::{Plugin, RequiredResource};
 use crate::renderer::camera::CameraBuffer;
 use bevy_ecs::prelude::*;
 use nalgebra::{clamp, Matrix4, Perspective3, Point3, Vector2, Vector3};
@@ -12,27 +16,37 @@ use winit::window::Window;
 #[derive(Default, Resource)]
 pub struct Camera {
     pub position: Point3<f32>,
+    // Where the camera is drawn from, blended between the last two ticks
+    pub render_position: Point3<f32>,
     yaw: f32,
     pitch: f32,
     sensitivity: f32,
+    fov: f32,
 }
 
 impl Camera {
     pub const WORLD_UP: Vector3<f32> = Vector3::new(0.0, 1.0, 0.0);
 
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
+        let position = Point3::from_slice(&[
+            Chunk::CHUNK_SIDE_SIZE / 2.0,
+            5.0,
+            Chunk::CHUNK_SIDE_SIZE / 2.0,
+        ]);
         Self {
-            position: Point3::from_slice(&[
-                Chunk::CHUNK_SIDE_SIZE / 2.0,
-                5.0,
-                Chunk::CHUNK_SIDE_SIZE / 2.0,
-            ]),
+            position,
+            render_position: position,
             yaw: -90.0,
             pitch: 0.0,
-            sensitivity: 1.0,
+            sensitivity: config.mouse_sensitivity,
+            fov: config.fov,
         }
     }
 
+    pub fn get_chunk_coord(&self) -> ChunkPos {
+        ChunkPos::from_world_coord(&self.position)
+    }
+
     pub fn get_direction(&self) -> Vector3<f32> {
         let yaw_cos = self.yaw.to_radians().cos();
         let yaw_sin = self.yaw.to_radians().sin();
@@ -57,7 +71,7 @@ impl Camera {
     }
 
     pub fn update(&mut self, offset: &Vector2<f32>, time_elapsed: f32) {
-        let timed_offset = offset * time_elapsed * 10.0;
+        let timed_offset = offset * time_elapsed * 10.0 * self.sensitivity;
 
         self.yaw -= timed_offset.x;
         self.pitch += timed_offset.y;
@@ -67,14 +81,14 @@ impl Camera {
     pub fn get_view_matrix(&self) -> Matrix4<f32> {
         let direction = self.get_direction();
         Matrix4::look_at_rh(
-            &self.position,
-            &(&self.position + direction),
+            &self.render_position,
+            &(&self.render_position + direction),
             &Self::WORLD_UP,
         )
     }
 
     pub fn get_projection_matrix(&self, aspect_ratio: f32) -> Matrix4<f32> {
-        Perspective3::new(aspect_ratio, 90.0f32.to_radians(), 0.1, 1000.0).to_homogeneous()
+        Perspective3::new(aspect_ratio, self.fov.to_radians(), 0.1, 1000.0).to_homogeneous()
     }
 
     pub fn get_yaw_pitch(&self) -> (f32, f32) {
@@ -85,7 +99,7 @@ impl Camera {
         CameraBuffer {
             projection: self.get_projection_matrix(aspect_ratio),
             view: self.get_view_matrix(),
-            position: self.position,
+            position: self.render_position,
             _p0: 0.0,
         }
     }
@@ -98,9 +112,36 @@ pub fn sync_camera(input_manager: Res<InputManager>, mut camera: ResMut<Camera>,
 
 pub struct CameraPlugin;
 
+impl CameraPlugin {
+    pub const LABEL: &'static str = "camera";
+}
+
 impl Plugin for CameraPlugin {
-    fn register_init(&self, world: &mut World, _init_schedule: &mut Schedule, _window: &Window) {
-        world.insert_resource(Camera::new());
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[InputPlugin::LABEL]
+    }
+
+    fn required_resources(&self) -> Vec<RequiredResource> {
+        vec![
+            RequiredResource::of::<Config>(),
+            RequiredResource::of::<InputManager>(),
+            RequiredResource::of::<Time>(),
+        ]
+    }
+
+    fn register_init(
+        &self,
+        world: &mut World,
+        _init_schedule: &mut Schedule,
+        _window: Option<&Window>,
+    ) -> Result<(), AppError> {
+        let camera = Camera::new(world.resource::<Config>());
+        world.insert_resource(camera);
+        Ok(())
     }
 
     fn register_runtime(&self, _world: &mut World, schedule: &mut Schedule) {
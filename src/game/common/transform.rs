@@ -1,13 +1,15 @@
-use nalgebra::{Matrix4, Rotation3, Translation3};
+use nalgebra::{Matrix4, Rotation3, Translation3, Vector3};
 
 pub struct Transform {
     pub rotation: Rotation3<f32>,
     pub translation: Translation3<f32>,
-    // Doesn't really need scale for now
+    pub scale: Vector3<f32>,
 }
 
 impl Transform {
     pub fn get_transformation_matrix(&self) -> Matrix4<f32> {
-        self.translation.to_homogeneous() * self.rotation.to_homogeneous()
+        self.translation.to_homogeneous()
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
     }
 }
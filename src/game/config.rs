@@ -1,18 +1,135 @@
+use crate::game::systems::FixedTime;
+use bevy_ecs::prelude::*;
 use rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
+#[derive(Debug)]
+pub enum ConfigError {
+    IOError,
+    ParseError(ron::error::SpannedError),
+    // Name of the setting
+    InvalidValue(&'static str),
+}
+
+// Missing fields in the settings file fall back to their default value
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
 pub struct Config {
+    // Window
+    pub window_width: u32,
+    pub window_height: u32,
+
+    // Camera
+    pub fov: f32,
+    pub mouse_sensitivity: f32,
+
+    // World
+    pub seed: u64,
+    // Chunks are generated on the fly and never saved when there is no world directory
+    pub world_path: Option<PathBuf>,
+    pub render_distance: usize,
+    // Distance in chunks from which chunks are meshed at half resolution, the resolution halves
+    // again every time the distance doubles. 0 meshes every chunk at full resolution.
+    pub lod_distance: usize,
+    pub worker_threads: usize,
+    // Chunks generated or read from the world directory at the same time
+    pub max_loading_chunks: usize,
+    // Blocks picked at random in every loaded section each tick for their random update
+    pub random_ticks_per_section: usize,
+
     // Physics
+    pub ticks_per_second: f32,
     pub physics_pipeline_active: bool,
     pub query_pipeline_active: bool,
     pub gravity: Vector<Real>,
 }
 
 impl Config {
-    fn new() -> Self {
+    pub const PATH: &'static str = "boxcraft.ron";
+
+    pub fn new() -> Self {
         Self {
+            window_width: 1024,
+            window_height: 768,
+            fov: 90.0,
+            mouse_sensitivity: 1.0,
+            seed: 0,
+            world_path: None,
+            render_distance: 3,
+            lod_distance: 4,
+            worker_threads: 2,
+            max_loading_chunks: 8,
+            random_ticks_per_section: 3,
+            ticks_per_second: FixedTime::DEFAULT_TICKS_PER_SECOND,
             physics_pipeline_active: true,
             query_pipeline_active: true,
             gravity: Vector::new(0.0, -9.81, 0.0),
         }
     }
+
+    // Uses the default settings when the file does not exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path.as_ref()) {
+            Ok(content) => Self::parse(&content),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                log::info!("No settings at {:?}, using defaults", path.as_ref());
+                Ok(Self::new())
+            }
+            Err(_) => Err(ConfigError::IOError),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, ConfigError> {
+        let config: Self = ron::from_str(content).map_err(ConfigError::ParseError)?;
+        // The length of a tick is worked out from it
+        if !(config.ticks_per_second.is_finite() && config.ticks_per_second > 0.0) {
+            return Err(ConfigError::InvalidValue("ticks_per_second"));
+        }
+        Ok(config)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, ConfigError};
+
+    #[test]
+    fn partial_settings_use_defaults() {
+        let config =
+            Config::parse("(render_distance: 8, fov: 70.0, gravity: [0.0, -1.0, 0.0])").unwrap();
+
+        assert_eq!(config.render_distance, 8);
+        assert_eq!(config.fov, 70.0);
+        assert_eq!(config.gravity.y, -1.0);
+        assert_eq!(config.window_width, Config::new().window_width);
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert!(Config::parse("(render_distance: \"far\")").is_err());
+        for ticks_per_second in ["0.0", "-20.0", "NaN", "inf"] {
+            assert!(matches!(
+                Config::parse(&format!("(ticks_per_second: {})", ticks_per_second)),
+                Err(ConfigError::InvalidValue("ticks_per_second"))
+            ));
+        }
+    }
+
+    #[test]
+    fn shipped_settings_match_defaults() {
+        assert_eq!(Config::load(Config::PATH).unwrap(), Config::new());
+    }
+
+    #[test]
+    fn missing_file_uses_defaults() {
+        assert_eq!(Config::load("does-not-exist.ron").unwrap(), Config::new());
+    }
 }
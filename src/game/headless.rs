@@ -0,0 +1,281 @@
+use crate::app::error::AppError;
+use crate::app::{App, Runtime};
+use crate::boxworld::registry::BlockRegistry;
+use crate::boxworld::systems::worker::update_worker;
+use crate::boxworld::BoxWorld;
+use crate::game::camera::Camera;
+use crate::game::systems::Time;
+use crate::renderer::game_renderer::GameRenderer;
+use crate::renderer::plugins::RendererPlugin;
+use crate::renderer::Renderer;
+use crate::ui::plugin::UIPlugin;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::{RunSystemOnce, SystemState};
+use image::RgbaImage;
+use std::time::Duration;
+use winit::dpi::PhysicalSize;
+
+pub struct HeadlessGame {
+    world: World,
+    tick_schedule: Schedule,
+    frame_schedule: Schedule,
+    ticks: u64,
+}
+
+impl HeadlessGame {
+    // Every default plugin except the renderer and the UI, both of which need a window
+    pub fn new() -> Self {
+        Self::with_app(
+            App::with_default_plugins()
+                .remove_plugin(RendererPlugin::LABEL)
+                .remove_plugin(UIPlugin::LABEL),
+        )
+        .unwrap()
+    }
+
+    // Renders into an offscreen target, preferably on the software adapter
+    pub fn with_renderer(size: PhysicalSize<u32>) -> Self {
+        Self::with_app(
+            App::with_default_plugins()
+                .remove_plugin(RendererPlugin::LABEL)
+                .remove_plugin(UIPlugin::LABEL)
+                .add_plugin(RendererPlugin::offscreen(size)),
+        )
+        .unwrap()
+    }
+
+    pub fn with_app(app: App) -> Result<Self, AppError> {
+        let Runtime {
+            world,
+            tick_schedule,
+            frame_schedule,
+        } = app.build(None)?;
+
+        Ok(Self {
+            world,
+            tick_schedule,
+            frame_schedule,
+            ticks: 0,
+        })
+    }
+
+    pub fn run_ticks(&mut self, ticks: u64, dt: Duration) {
+        for _ in 0..ticks {
+            self.world.insert_resource(Time::from(dt));
+            self.tick_schedule.run(&mut self.world);
+            self.ticks += 1;
+        }
+    }
+
+    // Blocks until every enqueued chunk has been generated, inserted into the world and meshed
+    pub fn wait_for_chunks(&mut self) {
+        while self.world.resource::<BoxWorld>().has_pending_chunks() {
+            self.world.run_system_once(update_worker);
+            std::thread::yield_now();
+        }
+        self.world
+            .resource_scope(|world, mut box_world: Mut<BoxWorld>| {
+                box_world.update_meshes(world.resource::<BlockRegistry>())
+            });
+    }
+
+    pub fn screenshot(&mut self) -> RgbaImage {
+        self.frame_schedule.run(&mut self.world);
+
+        let mut state =
+            SystemState::<(Res<Camera>, Res<GameRenderer>, ResMut<Renderer>)>::new(&mut self.world);
+        let (camera, game_renderer, mut renderer) = state.get_mut(&mut self.world);
+
+        renderer.capture(&camera, None, &game_renderer)
+    }
+
+    pub fn get_ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+}
+
+// Nothing edited is lost when a game is dropped without being saved
+impl Drop for HeadlessGame {
+    fn drop(&mut self) {
+        if let Some(mut box_world) = self.world.get_resource_mut::<BoxWorld>() {
+            box_world.save_modified_chunks();
+        }
+    }
+}
+
+impl Default for HeadlessGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HeadlessGame;
+    use crate::app::error::AppError;
+    use crate::app::App;
+    use crate::boxworld::position::{BlockPos, ChunkPos};
+    use crate::boxworld::registry::BlockRegistry;
+    use crate::boxworld::storage::{StorageError, WorldStorage};
+    use crate::boxworld::BoxWorld;
+    use crate::boxworld::WorldError;
+    use crate::game::camera::Camera;
+    use crate::game::config::Config;
+    use crate::game::player::Player;
+    use crate::physic::Physics;
+    use crate::renderer::plugins::RendererPlugin;
+    use crate::ui::plugin::UIPlugin;
+    use rapier3d::prelude::RigidBodyHandle;
+    use std::path::Path;
+    use std::time::Duration;
+
+    const DT: Duration = Duration::from_millis(16);
+
+    #[test]
+    fn flying_player_stays_in_place() {
+        let mut game = HeadlessGame::new();
+        let start = game.world().resource::<Camera>().position;
+
+        game.run_ticks(30, DT);
+
+        assert_eq!(game.get_ticks(), 30);
+        assert_eq!(game.world().resource::<Camera>().position, start);
+        assert!(game.world().resource::<Player>().flying);
+        assert_eq!(
+            *game
+                .world()
+                .resource::<BoxWorld>()
+                .get_current_chunk_coord(),
+            ChunkPos::from_world_coord(&start)
+        );
+    }
+
+    fn start_falling(game: &mut HeadlessGame) -> RigidBodyHandle {
+        let world = game.world_mut();
+        let rb_handle = {
+            let mut player = world.resource_mut::<Player>();
+            player.flying = false;
+            player.rb_handle
+        };
+        world
+            .resource_mut::<Physics>()
+            .rigid_body_set
+            .get_mut(rb_handle)
+            .unwrap()
+            .set_gravity_scale(1.0, true);
+        rb_handle
+    }
+
+    #[test]
+    fn falling_player_moves_camera() {
+        let mut game = HeadlessGame::new();
+        let start = game.world().resource::<Camera>().position;
+        let rb_handle = start_falling(&mut game);
+
+        game.run_ticks(30, DT);
+
+        let physics = game.world().resource::<Physics>();
+        assert!(physics.rigid_body_set[rb_handle].translation().y < start.y);
+        assert!(game.world().resource::<Camera>().position.y < start.y);
+    }
+
+    #[test]
+    fn water_holds_up_a_falling_player() {
+        let mut games = [HeadlessGame::new(), HeadlessGame::new()];
+        for game in games.iter_mut() {
+            game.run_ticks(1, DT);
+            game.wait_for_chunks();
+        }
+        let start = games[0].world().resource::<Camera>().position;
+        let water = games[1]
+            .world()
+            .resource::<BlockRegistry>()
+            .get_id("water")
+            .unwrap();
+        let mut box_world = games[1].world_mut().resource_mut::<BoxWorld>();
+        let center = BlockPos::from_world_coord(&start);
+        for x in -2..=2 {
+            for y in -30..=0 {
+                for z in -2..=2 {
+                    assert!(box_world.set_block(center.offset(x, y, z), water));
+                }
+            }
+        }
+
+        for game in games.iter_mut() {
+            start_falling(game);
+            game.run_ticks(60, DT);
+        }
+        let fall = |game: &HeadlessGame| start.y - game.world().resource::<Camera>().position.y;
+        assert!(fall(&games[0]) > 3.0);
+        // Floats around the surface
+        assert!(fall(&games[1]).abs() < 1.0);
+    }
+
+    fn with_world_path(path: &Path) -> Result<HeadlessGame, AppError> {
+        let config = Config {
+            world_path: Some(path.to_path_buf()),
+            ..Config::default()
+        };
+        HeadlessGame::with_app(
+            App::with_default_plugins()
+                .insert_resource(config)
+                .remove_plugin(RendererPlugin::LABEL)
+                .remove_plugin(UIPlugin::LABEL),
+        )
+    }
+
+    #[test]
+    fn corrupt_chunk_is_generated_again() {
+        let path = std::env::temp_dir().join("boxcraft-corrupt-chunk-test");
+        let _ = std::fs::remove_dir_all(&path);
+        // Where the camera starts
+        let chunk_coord = ChunkPos::new(0, 0);
+        let chunk_path = path
+            .join(WorldStorage::CHUNK_DIRECTORY)
+            .join(format!("{}_{}.chunk", chunk_coord.x, chunk_coord.z));
+        std::fs::create_dir_all(chunk_path.parent().unwrap()).unwrap();
+        // A chunk of an older format
+        std::fs::write(&chunk_path, [1, 2, 3]).unwrap();
+
+        let mut game = with_world_path(&path).unwrap();
+        game.run_ticks(1, DT);
+        game.wait_for_chunks();
+        assert!(game
+            .world()
+            .resource::<BoxWorld>()
+            .get_chunk(&chunk_coord)
+            .is_some());
+
+        // Not a directory the world can be stored in
+        let file_path = path.join("level.ron");
+        assert_eq!(
+            with_world_path(&file_path).err(),
+            Some(AppError::World(WorldError::Storage(StorageError::IOError)))
+        );
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn falling_player_is_deterministic() {
+        let mut games = [HeadlessGame::new(), HeadlessGame::new()];
+        for game in games.iter_mut() {
+            start_falling(game);
+            game.run_ticks(45, DT);
+        }
+
+        assert_eq!(
+            games[0].world().resource::<Camera>().position,
+            games[1].world().resource::<Camera>().position
+        );
+    }
+}
@@ -1,11 +1,13 @@
 pub mod camera;
 pub mod common;
 pub mod config;
+pub mod headless;
 pub mod player;
 pub mod schedule;
 pub mod systems;
 
-use crate::game::camera::{Camera, CameraPlugin};
+use crate::game::camera::Camera;
+use crate::game::config::Config;
 use crate::ui::UI;
 use std::sync::mpsc::{channel, Receiver, Sender};
 
@@ -14,26 +16,18 @@ use crate::misc::window::Window;
 use crate::renderer::Renderer;
 use bevy_ecs::prelude::*;
 
-use futures_lite::future;
-
 use bevy_ecs::system::SystemState;
 use std::time::Instant;
 
 use crate::boxworld::chunk::Chunk;
+use crate::boxworld::BoxWorld;
 
-use crate::game::player::{update_player, update_player_toggle_fly, PlayerPlugin};
-use crate::game::systems::Time;
-
+use crate::game::systems::{FixedTime, Time};
 
-use crate::app::input::plugin::InputPlugin;
-use crate::boxworld::plugin::WorldPlugin;
-use crate::physic::plugin::PhysicsPlugin;
-use crate::plugin::Plugin;
+use crate::app::error::AppError;
+use crate::app::{App, Runtime};
 use crate::renderer::game_renderer::GameRenderer;
-use crate::renderer::plugins::RendererPlugin;
-use crate::ui::plugin::UIPlugin;
-use crate::worker::plugins::WorkerPlugin;
-use schedule::ScheduleStage;
+use crate::renderer::screenshot;
 use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 
@@ -41,9 +35,11 @@ pub struct Game {
     event_loop: Option<EventLoop<()>>,
     window: Window,
     is_cursor_locked: bool,
+    is_screenshot_requested: bool,
 
     world: World,
-    schedule: Schedule,
+    tick_schedule: Schedule,
+    frame_schedule: Schedule,
 
     chunk_rx: Receiver<Chunk>,
     to_world_tx: Sender<Chunk>,
@@ -52,66 +48,37 @@ pub struct Game {
 
 impl Game {
     pub fn new() -> Self {
+        let config = Config::load(Config::PATH).unwrap();
+        Self::with_app(App::with_default_plugins().insert_resource(config)).unwrap()
+    }
+
+    pub fn with_app(app: App) -> Result<Self, AppError> {
         let event_loop = EventLoop::new();
-        let window = Window::new(&event_loop);
-
-        let mut world = World::new();
-
-        let plugins: &[&dyn Plugin] = &[
-            &InputPlugin,
-            &PhysicsPlugin,
-            &CameraPlugin,
-            &PlayerPlugin,
-            &RendererPlugin,
-            &WorldPlugin,
-            &UIPlugin,
-            &WorkerPlugin,
-        ];
-        let mut init_schedule = Schedule::default();
-        for plugin in plugins.iter() {
-            plugin.register_init(&mut world, &mut init_schedule, &window);
-        }
-        init_schedule.run(&mut world);
-
-        let camera = world.get_resource::<Camera>().unwrap();
-        let r = future::block_on(Renderer::new(&window));
-        let gr = GameRenderer::new(&r.render_context, &window, camera);
-        world.insert_resource(gr);
-        world.insert_resource(r);
-        world.insert_resource(Time::new());
-
-        let mut schedule = Schedule::default();
-        for plugin in plugins.iter() {
-            plugin.register_runtime(&mut world, &mut schedule);
-        }
-        schedule
-            .add_systems(update_player)
-            .add_systems(update_player_toggle_fly)
-            .configure_sets(
-                (
-                    ScheduleStage::PreUpdate,
-                    ScheduleStage::Update,
-                    ScheduleStage::PostUpdate,
-                    ScheduleStage::PreRender,
-                    ScheduleStage::Render,
-                )
-                    .chain(),
-            );
+        let config = app.resource::<Config>();
+        let window = Window::new(&event_loop, config.window_width, config.window_height);
+
+        let Runtime {
+            world,
+            tick_schedule,
+            frame_schedule,
+        } = app.build(Some(&window))?;
 
         log::info!("Main thread {:?}", std::thread::current().id());
 
         let (to_world_tx, chunk_rx) = channel();
 
-        Self {
+        Ok(Self {
             event_loop: Some(event_loop),
             window,
             world,
-            schedule,
+            tick_schedule,
+            frame_schedule,
             is_cursor_locked: true,
+            is_screenshot_requested: false,
             chunk_rx,
             to_world_tx,
             time_start: Instant::now(),
-        }
+        })
     }
 
     pub fn run_loop(mut self) {
@@ -165,7 +132,10 @@ impl Game {
                         ..
                     },
                 ..
-            } => *control_flow = ControlFlow::Exit,
+            } => {
+                self.world.resource_mut::<BoxWorld>().save_modified_chunks();
+                *control_flow = ControlFlow::Exit;
+            }
             WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
@@ -178,6 +148,17 @@ impl Game {
                 self.is_cursor_locked = !self.is_cursor_locked;
                 // self.window.set_cursor_grab(CursorGrabMode::Locked).unwrap();
             }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F12),
+                        ..
+                    },
+                ..
+            } => {
+                self.is_screenshot_requested = true;
+            }
             WindowEvent::Resized(ref new_inner_size) => {
                 self.window.on_resized(new_inner_size);
                 let mut renderer = self.world.get_resource_mut::<Renderer>().unwrap();
@@ -209,9 +190,17 @@ impl Game {
         let time_elapsed = self.time_start.elapsed();
         self.time_start = Instant::now();
 
-        self.world.insert_resource(Time::from(time_elapsed));
+        let mut fixed_time = self.world.resource_mut::<FixedTime>();
+        fixed_time.accumulate(time_elapsed);
+        let step = fixed_time.step();
+
+        self.world.insert_resource(Time::from(step));
+        while self.world.resource_mut::<FixedTime>().expend() {
+            self.tick_schedule.run(&mut self.world);
+        }
 
-        self.schedule.run(&mut self.world);
+        self.world.insert_resource(Time::from(time_elapsed));
+        self.frame_schedule.run(&mut self.world);
 
         let mut state = SystemState::<(
             ResMut<Camera>,
@@ -222,6 +211,16 @@ impl Game {
         let (camera, game_renderer, mut ui, mut renderer) = state.get_mut(&mut self.world);
 
         let ui_render_data = ui.get_draw_data(&self.window);
-        renderer.render(&camera, &self.window, ui_render_data, &game_renderer);
+        if self.is_screenshot_requested {
+            self.is_screenshot_requested = false;
+            let image = renderer.render_and_capture(&camera, ui_render_data, &game_renderer);
+            let path = screenshot::default_path();
+            match screenshot::save(&image, &path) {
+                Ok(_) => log::info!("Screenshot saved to {}", path.display()),
+                Err(err) => log::error!("Failed to save screenshot: {:?}", err),
+            }
+        } else {
+            renderer.render(&camera, ui_render_data, &game_renderer);
+        }
     }
 }
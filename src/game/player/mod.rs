@@ -1,10 +1,18 @@
-use crate::game::camera::Camera;
-
+use crate::boxworld::plugin::WorldPlugin;
+use crate::boxworld::position::BlockPos;
+use crate::boxworld::registry::BlockRegistry;
+use crate::boxworld::BoxWorld;
+use crate::game::camera::{Camera, CameraPlugin};
+
+use crate::app::error::AppError;
+use crate::app::input::plugin::InputPlugin;
 use crate::app::input::InputManager;
 
-use crate::game::systems::Time;
-use crate::physic::Physics;
-use crate::plugin::Plugin;
+use crate::game::schedule::ScheduleStage;
+use crate::game::systems::{FixedTime, Time};
+use crate::physic::plugin::PhysicsPlugin;
+use crate::physic::{update_physics, Physics};
+use crate::plugin::{Plugin, RequiredResource};
 use bevy_ecs::prelude::*;
 use rapier3d::prelude::*;
 use winit::event::VirtualKeyCode;
@@ -81,10 +89,70 @@ pub fn update_player(
     rb.set_translation(translation, false);
 }
 
+// A player in a fluid is pushed up against the gravity and slowed down, from the block its center
+// is in
+pub fn update_player_fluid(
+    player: Res<Player>,
+    box_world: Res<BoxWorld>,
+    registry: Res<BlockRegistry>,
+    mut physics: ResMut<Physics>,
+) {
+    let gravity = *physics.get_gravity();
+    let rb = physics.rigid_body_set.get_mut(player.rb_handle).unwrap();
+    let pos = BlockPos::from_world_coord(&Point::from(*rb.translation()));
+    let fluid = box_world
+        .get_block(pos)
+        .and_then(|block| registry.get_fluid(block.id));
+
+    rb.reset_forces(false);
+    match fluid {
+        Some(fluid) if !player.flying => {
+            let buoyancy = -gravity * rb.mass() * fluid.buoyancy;
+            rb.add_force(buoyancy, true);
+            rb.set_linear_damping(fluid.drag);
+        }
+        _ => rb.set_linear_damping(0.0),
+    }
+}
+
 pub struct PlayerPlugin;
 
+impl PlayerPlugin {
+    pub const LABEL: &'static str = "player";
+}
+
 impl Plugin for PlayerPlugin {
-    fn register_init(&self, _world: &mut World, init_schedule: &mut Schedule, _window: &Window) {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[
+            InputPlugin::LABEL,
+            PhysicsPlugin::LABEL,
+            CameraPlugin::LABEL,
+            WorldPlugin::LABEL,
+        ]
+    }
+
+    fn required_resources(&self) -> Vec<RequiredResource> {
+        vec![
+            RequiredResource::of::<InputManager>(),
+            RequiredResource::of::<Physics>(),
+            RequiredResource::of::<Camera>(),
+            RequiredResource::of::<BoxWorld>(),
+            RequiredResource::of::<BlockRegistry>(),
+            RequiredResource::of::<Time>(),
+            RequiredResource::of::<FixedTime>(),
+        ]
+    }
+
+    fn register_init(
+        &self,
+        _world: &mut World,
+        init_schedule: &mut Schedule,
+        _window: Option<&Window>,
+    ) -> Result<(), AppError> {
         pub fn init_player(
             mut commands: Commands,
             mut physics: ResMut<Physics>,
@@ -107,6 +175,7 @@ impl Plugin for PlayerPlugin {
         }
 
         init_schedule.add_systems(init_player);
+        Ok(())
     }
 
     fn register_runtime(&self, _world: &mut World, schedule: &mut Schedule) {
@@ -119,6 +188,32 @@ impl Plugin for PlayerPlugin {
             camera.position = Point::from(*rb.translation());
         }
 
-        schedule.add_systems(update_player_physics);
+        schedule
+            .add_systems(
+                (update_player, update_player_toggle_fly, update_player_fluid)
+                    .in_set(ScheduleStage::Update),
+            )
+            .add_systems(
+                update_player_physics
+                    .in_set(ScheduleStage::PostUpdate)
+                    .after(update_physics),
+            );
+    }
+
+    fn register_frame(&self, _world: &mut World, schedule: &mut Schedule) {
+        pub fn interpolate_camera(
+            player: Res<Player>,
+            physics: Res<Physics>,
+            fixed_time: Res<FixedTime>,
+            mut camera: ResMut<Camera>,
+        ) {
+            if let Some(position) =
+                physics.get_interpolated_position(player.rb_handle, fixed_time.alpha())
+            {
+                camera.render_position = Point::from(position.translation.vector);
+            }
+        }
+
+        schedule.add_systems(interpolate_camera.in_set(ScheduleStage::PreRender));
     }
 }
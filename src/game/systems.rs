@@ -98,3 +98,50 @@ impl From<Duration> for Time {
         }
     }
 }
+
+#[derive(Resource)]
+pub struct FixedTime {
+    step: Duration,
+    accumulator: Duration,
+}
+
+impl FixedTime {
+    pub const DEFAULT_TICKS_PER_SECOND: f32 = 60.0;
+    // Frames slower than this drop simulation time instead of running ever more ticks to catch up
+    pub const MAX_ACCUMULATED: Duration = Duration::from_millis(250);
+
+    pub fn from_ticks_per_second(ticks_per_second: f32) -> Self {
+        Self {
+            step: Duration::from_secs_f32(1.0 / ticks_per_second),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn accumulate(&mut self, time_elapsed: Duration) {
+        self.accumulator = (self.accumulator + time_elapsed).min(Self::MAX_ACCUMULATED);
+    }
+
+    pub fn expend(&mut self) -> bool {
+        if self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            true
+        } else {
+            false
+        }
+    }
+
+    // How far the current frame is between the last two ticks, in the range [0, 1)
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+impl Default for FixedTime {
+    fn default() -> Self {
+        Self::from_ticks_per_second(Self::DEFAULT_TICKS_PER_SECOND)
+    }
+}
@@ -1,4 +1,4 @@
-mod app;
+pub mod app;
 pub mod boxworld;
 pub mod game;
 pub mod misc;
@@ -9,11 +9,24 @@ pub mod ui;
 pub mod utils;
 mod worker;
 
+use crate::app::cli::Cli;
+use crate::app::App;
 use crate::game::Game;
+use clap::Parser;
 
 fn main() {
     env_logger::init();
 
-    let game = Game::new();
-    game.run_loop();
+    let cli = Cli::parse();
+    let config = cli.load_config().unwrap();
+
+    let result = if cli.headless {
+        cli.run_headless(config)
+    } else {
+        Game::with_app(App::with_default_plugins().insert_resource(config)).map(Game::run_loop)
+    };
+    if let Err(err) = result {
+        log::error!("Failed to start the game: {:?}", err);
+        std::process::exit(1);
+    }
 }
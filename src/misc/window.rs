@@ -9,16 +9,13 @@ pub struct Window {
 }
 
 impl Window {
-    pub fn new<T>(event_loop: &EventLoop<T>) -> Self {
-        let window_inner_size = PhysicalSize {
-            width: 1024.0,
-            height: 768.0,
-        };
+    pub fn new<T>(event_loop: &EventLoop<T>, width: u32, height: u32) -> Self {
+        let window_inner_size = PhysicalSize { width, height };
         let window = WindowBuilder::new()
             .with_inner_size(window_inner_size)
             .build(event_loop)
             .unwrap();
-        let aspect_ratio = window_inner_size.width / window_inner_size.height;
+        let aspect_ratio = width as f32 / height as f32;
         Self {
             window,
             aspect_ratio,
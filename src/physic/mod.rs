@@ -1,8 +1,10 @@
 pub mod plugin;
 
 use rapier3d::prelude::*;
+use std::collections::HashMap;
 
-
+use crate::game::config::Config;
+use crate::game::systems::Time;
 use bevy_ecs::prelude::*;
 
 #[derive(Resource)]
@@ -17,13 +19,16 @@ pub struct Physics {
     impulse_joint_set: ImpulseJointSet,
     multibody_joint_set: MultibodyJointSet,
     ccd_solver: CCDSolver,
+    query_pipeline: Option<QueryPipeline>,
     physics_hooks: (),
     event_handler: (),
     gravity: Vector<Real>,
+    is_active: bool,
+    previous_positions: HashMap<RigidBodyHandle, Isometry<Real>>,
 }
 
 impl Physics {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         Self {
             rigid_body_set: RigidBodySet::new(),
             collider_set: ColliderSet::new(),
@@ -34,14 +39,36 @@ impl Physics {
             narrow_phase: NarrowPhase::new(),
             impulse_joint_set: ImpulseJointSet::new(),
             multibody_joint_set: MultibodyJointSet::new(),
-            gravity: Vector::new(0.0, -9.81, 0.0),
+            query_pipeline: config.query_pipeline_active.then(QueryPipeline::new),
+            gravity: config.gravity,
+            is_active: config.physics_pipeline_active,
             event_handler: (),
             physics_hooks: (),
             ccd_solver: Default::default(),
+            previous_positions: HashMap::new(),
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn get_gravity(&self) -> &Vector<Real> {
+        &self.gravity
+    }
+
+    // Remembers where every rigid body is before a tick moves it, for render interpolation
+    pub fn snapshot_positions(&mut self) {
+        self.previous_positions.clear();
+        self.previous_positions.extend(
+            self.rigid_body_set
+                .iter()
+                .map(|(handle, rb)| (handle, *rb.position())),
+        );
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if !self.is_active {
+            return;
+        }
+
+        self.integration_parameters.dt = dt;
         self.physics_pipeline.step(
             &self.gravity,
             &self.integration_parameters,
@@ -53,13 +80,30 @@ impl Physics {
             &mut self.impulse_joint_set,
             &mut self.multibody_joint_set,
             &mut self.ccd_solver,
-            None,
+            self.query_pipeline.as_mut(),
             &self.physics_hooks,
             &self.event_handler,
         );
     }
+
+    // Position of a rigid body blended between the last two steps
+    pub fn get_interpolated_position(
+        &self,
+        rb_handle: RigidBodyHandle,
+        alpha: f32,
+    ) -> Option<Isometry<Real>> {
+        let position = self.rigid_body_set.get(rb_handle)?.position();
+        Some(match self.previous_positions.get(&rb_handle) {
+            Some(previous_position) => previous_position.lerp_slerp(position, alpha),
+            None => *position,
+        })
+    }
+}
+
+pub fn snapshot_physics(mut physics: ResMut<Physics>) {
+    physics.snapshot_positions();
 }
 
-pub fn update_physics(mut physics: ResMut<Physics>) {
-    physics.update();
+pub fn update_physics(mut physics: ResMut<Physics>, time: Res<Time>) {
+    physics.update(time.dt);
 }
@@ -1,19 +1,46 @@
+use crate::app::error::AppError;
+use crate::game::config::Config;
 use crate::game::schedule::ScheduleStage;
-use crate::physic::update_physics;
+use crate::game::systems::Time;
 use crate::physic::Physics;
-use crate::plugin::Plugin;
+use crate::physic::{snapshot_physics, update_physics};
+use crate::plugin::{Plugin, RequiredResource};
 use bevy_ecs::prelude::*;
 use winit::window::Window;
 
 pub struct PhysicsPlugin;
 
+impl PhysicsPlugin {
+    pub const LABEL: &'static str = "physics";
+}
+
 impl Plugin for PhysicsPlugin {
-    fn register_init(&self, world: &mut World, _init_schedule: &mut Schedule, _window: &Window) {
-        world.insert_resource(Physics::new());
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn required_resources(&self) -> Vec<RequiredResource> {
+        vec![
+            RequiredResource::of::<Config>(),
+            RequiredResource::of::<Time>(),
+        ]
+    }
+
+    fn register_init(
+        &self,
+        world: &mut World,
+        _init_schedule: &mut Schedule,
+        _window: Option<&Window>,
+    ) -> Result<(), AppError> {
+        let physics = Physics::new(world.resource::<Config>());
+        world.insert_resource(physics);
+        Ok(())
     }
 
     fn register_runtime(&self, _world: &mut World, schedule: &mut Schedule) {
-        schedule.add_systems(update_physics.in_set(ScheduleStage::PostUpdate));
+        schedule
+            .add_systems(snapshot_physics.in_set(ScheduleStage::PreUpdate))
+            .add_systems(update_physics.in_set(ScheduleStage::PostUpdate));
     }
 }
 
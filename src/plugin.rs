@@ -1,9 +1,53 @@
+use crate::app::error::AppError;
 use bevy_ecs::prelude::*;
 use winit::window::Window;
 
 pub trait Plugin {
+    fn label(&self) -> &'static str;
+    // Labels of the plugins that have to be initialized before this one
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+    // Resources that have to exist once every plugin is initialized
+    fn required_resources(&self) -> Vec<RequiredResource> {
+        Vec::new()
+    }
+    // Fails when a resource cannot be created, the app is not built then
     #[allow(unused_variables)]
-    fn register_init(&self, world: &mut World, schedule: &mut Schedule, window: &Window) {}
+    fn register_init(
+        &self,
+        world: &mut World,
+        schedule: &mut Schedule,
+        window: Option<&Window>,
+    ) -> Result<(), AppError> {
+        Ok(())
+    }
+    // Systems that run at the fixed tick rate, in the PreUpdate, Update and PostUpdate stages
     #[allow(unused_variables)]
     fn register_runtime(&self, world: &mut World, schedule: &mut Schedule) {}
+    // Systems that run once per rendered frame, in the PreRender and Render stages
+    #[allow(unused_variables)]
+    fn register_frame(&self, world: &mut World, schedule: &mut Schedule) {}
+}
+
+pub struct RequiredResource {
+    pub name: &'static str,
+    is_present: fn(&World) -> bool,
+}
+
+impl RequiredResource {
+    pub fn of<R: Resource>() -> Self {
+        fn is_present<R: Resource>(world: &World) -> bool {
+            world.contains_resource::<R>()
+        }
+
+        Self {
+            name: std::any::type_name::<R>(),
+            is_present: is_present::<R>,
+        }
+    }
+
+    pub fn is_present(&self, world: &World) -> bool {
+        (self.is_present)(world)
+    }
 }
@@ -4,16 +4,14 @@ use crate::renderer::util::any_sized_as_u8_slice;
 use nalgebra::{Matrix4, Point3};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{BindingResource, Buffer};
-use winit::window::Window;
 
 pub struct CameraRenderer {
     buffer: Buffer,
 }
 
 impl CameraRenderer {
-    pub fn new(render_context: &RenderContext, window: &Window, camera: &Camera) -> Self {
-        let window_size = window.inner_size();
-        let aspect_ratio = window_size.width as f32 / window_size.height as f32;
+    pub fn new(render_context: &RenderContext, camera: &Camera) -> Self {
+        let aspect_ratio = render_context.aspect_ratio();
 
         let buffer = render_context
             .device
@@ -30,9 +28,8 @@ impl CameraRenderer {
         self.buffer.as_entire_binding()
     }
 
-    pub fn update(&self, render_context: &RenderContext, window: &Window, camera: &Camera) {
-        let window_size = window.inner_size();
-        let aspect_ratio = window_size.width as f32 / window_size.height as f32;
+    pub fn update(&self, render_context: &RenderContext, camera: &Camera) {
+        let aspect_ratio = render_context.aspect_ratio();
 
         render_context.queue.write_buffer(
             &self.buffer,
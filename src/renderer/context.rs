@@ -1,14 +1,13 @@
-use crate::misc::window::Window;
-
 use wgpu::{
     Backends, CommandEncoder, CommandEncoderDescriptor, Dx12Compiler, Instance, InstanceDescriptor,
     PresentMode, Surface, SurfaceConfiguration, SurfaceTexture, TextureFormat, TextureView,
     TextureViewDescriptor,
 };
 use winit::dpi::PhysicalSize;
+use winit::window::Window;
 
 pub struct RenderContext {
-    pub render_surface: Surface,
+    pub render_surface: Option<Surface>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub render_surface_config: SurfaceConfiguration,
@@ -21,7 +20,7 @@ impl RenderContext {
             backends: Backends::all(),
             dx12_shader_compiler: Dx12Compiler::default(),
         });
-        let render_surface = unsafe { instance.create_surface(&**window) }.unwrap();
+        let render_surface = unsafe { instance.create_surface(window) }.unwrap();
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
@@ -55,7 +54,7 @@ impl RenderContext {
         render_surface.configure(&device, &render_surface_config);
 
         Self {
-            render_surface,
+            render_surface: Some(render_surface),
             render_surface_config,
             queue,
             device,
@@ -63,27 +62,91 @@ impl RenderContext {
         }
     }
 
+    pub async fn new_offscreen(size: PhysicalSize<u32>) -> Self {
+        let instance = Instance::new(InstanceDescriptor {
+            backends: Backends::all(),
+            dx12_shader_compiler: Dx12Compiler::default(),
+        });
+        // Prefer the software adapter so headless runs behave the same on every machine, but
+        // fall back to whatever is available on platforms that don't ship one
+        let adapter = match instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: true,
+            })
+            .await
+        {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions::default())
+                .await
+                .unwrap(),
+        };
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features: adapter.features() & wgpu::Features::POLYGON_MODE_LINE,
+                    limits: wgpu::Limits::downlevel_defaults(),
+                    label: None,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        // Not used to configure anything, only to describe the offscreen target
+        let render_surface_config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: TextureFormat::Bgra8UnormSrgb,
+            width: size.width,
+            height: size.height,
+            present_mode: PresentMode::Fifo,
+            alpha_mode: Default::default(),
+            view_formats: Vec::new(),
+        };
+
+        Self {
+            render_surface: None,
+            render_surface_config,
+            queue,
+            device,
+            scale_factor: 1.0,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.render_surface_config.width as f32 / self.render_surface_config.height as f32
+    }
+
     pub fn resize(&mut self, new_window_size: &PhysicalSize<u32>) {
         self.render_surface_config.width = new_window_size.width;
         self.render_surface_config.height = new_window_size.height;
 
-        self.render_surface
-            .configure(&self.device, &self.render_surface_config);
+        if let Some(render_surface) = &self.render_surface {
+            render_surface.configure(&self.device, &self.render_surface_config);
+        }
     }
 
-    pub fn create_command_encoder(&self) -> (CommandEncoder, SurfaceTexture, TextureView) {
-        let texture_to_present = self.render_surface.get_current_texture().unwrap();
+    pub fn get_current_texture(&self) -> (SurfaceTexture, TextureView) {
+        let texture_to_present = self
+            .render_surface
+            .as_ref()
+            .unwrap()
+            .get_current_texture()
+            .unwrap();
         let texture_view_to_present = texture_to_present
             .texture
             .create_view(&TextureViewDescriptor::default());
 
-        let command_encoder = self
-            .device
+        (texture_to_present, texture_view_to_present)
+    }
+
+    pub fn create_command_encoder(&self) -> CommandEncoder {
+        self.device
             .create_command_encoder(&CommandEncoderDescriptor {
                 label: Some("Command encoder descriptor"),
-            });
-
-        (command_encoder, texture_to_present, texture_view_to_present)
+            })
 
         // {
         //     let mut render_pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
@@ -0,0 +1,67 @@
+use crate::game::camera::Camera;
+use nalgebra::{Matrix4, Point3, Vector4};
+
+// Volume seen by the camera, as the 6 planes bounding it. A point is inside a plane when
+// dot(plane.xyz, point) + plane.w is positive.
+#[derive(Clone, Debug)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Planes extracted from the rows of the view projection matrix (Gribb & Hartmann)
+    pub fn from_matrix(view_projection: &Matrix4<f32>) -> Self {
+        let row = |i: usize| view_projection.row(i).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z],
+        }
+    }
+
+    pub fn from_camera(camera: &Camera, aspect_ratio: f32) -> Self {
+        Self::from_matrix(&(camera.get_projection_matrix(aspect_ratio) * camera.get_view_matrix()))
+    }
+
+    // Conservative: a box crossing the corner of the frustum can be kept while outside
+    pub fn contains_aabb(&self, min: &Point3<f32>, max: &Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner of the box the furthest along the plane normal
+            let x = if plane.x >= 0.0 { max.x } else { min.x };
+            let y = if plane.y >= 0.0 { max.y } else { min.y };
+            let z = if plane.z >= 0.0 { max.z } else { min.z };
+            plane.x * x + plane.y * y + plane.z * z + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Frustum;
+    use crate::game::camera::Camera;
+    use crate::game::config::Config;
+    use nalgebra::{Point3, Vector3};
+
+    fn contains_block(frustum: &Frustum, center: Point3<f32>) -> bool {
+        let half = Vector3::from_element(0.5);
+        frustum.contains_aabb(&(center - half), &(center + half))
+    }
+
+    #[test]
+    fn only_boxes_in_front_of_the_camera_are_kept() {
+        // Looking toward -z from (8, 5, 8)
+        let camera = Camera::new(&Config::default());
+        let frustum = Frustum::from_camera(&camera, 1.0);
+
+        assert!(contains_block(&frustum, Point3::new(8.0, 5.0, -20.0)));
+        assert!(!contains_block(&frustum, Point3::new(8.0, 5.0, 30.0)));
+        assert!(!contains_block(&frustum, Point3::new(60.0, 5.0, -20.0)));
+        assert!(!contains_block(&frustum, Point3::new(8.0, 5.0, -2000.0)));
+
+        // A box around the camera is kept even though its corners are all outside
+        assert!(frustum.contains_aabb(
+            &Point3::new(-100.0, -100.0, -100.0),
+            &Point3::new(100.0, 100.0, 100.0)
+        ));
+    }
+}
@@ -1,50 +1,267 @@
 use crate::game::camera::Camera;
-use crate::misc::window::Window;
 use crate::renderer::camera::CameraRenderer;
 use crate::renderer::context::RenderContext;
 use crate::renderer::util::{any_sized_as_u8_slice, any_slice_as_u8_slice};
 use crate::renderer::vertex::{Vertex, VertexLike};
-use nalgebra::{Point3, Vector2, Vector3};
-
-use crate::boxworld::block::RawFaceInstance;
+use nalgebra::{Point3, Vector2, Vector3, Vector4};
+
+use crate::boxworld::block::{Block, FaceCenter, RawFaceInstance, RawModelFaceInstance};
+use crate::boxworld::chunk::Chunk;
+use crate::boxworld::mesher::SectionMesh;
+use crate::boxworld::position::ChunkPos;
+use crate::boxworld::visibility::{get_section_bounds, SectionPos};
+use crate::renderer::frustum::Frustum;
 use crate::renderer::texture::Texture;
 use bevy_ecs::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::ops::Range;
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{
-    include_spirv, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendState, Buffer,
-    BufferBindingType, BufferUsages, ColorTargetState, ColorWrites, CompareFunction,
-    DepthStencilState, Face, FragmentState, FrontFace, MultisampleState, PipelineLayout,
-    PolygonMode, RenderPass, RenderPipelineDescriptor, SamplerBindingType, ShaderModule,
-    ShaderModuleDescriptor, ShaderStages, TextureFormat, TextureSampleType, TextureViewDimension,
-    VertexBufferLayout,
+    include_spirv, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
+    BlendState, Buffer, BufferBindingType, BufferUsages, ColorTargetState, ColorWrites,
+    CompareFunction, DepthStencilState, Face, FragmentState, FrontFace, MultisampleState,
+    PipelineLayout, PolygonMode, RenderPass, RenderPipelineDescriptor, SamplerBindingType,
+    ShaderModule, ShaderModuleDescriptor, ShaderStages, TextureFormat, TextureSampleType,
+    TextureViewDimension, VertexBufferLayout,
 };
 
+// Faces of every section of a chunk in a single buffer
+struct FaceInstances<T> {
+    buffer: Buffer,
+    // Faces of every section in the buffer, from the bottom
+    section_instances: Vec<Range<u32>>,
+    // Copy of the buffer kept to sort the faces again when the camera moves, translucent faces only
+    sorted_instances: Option<Vec<T>>,
+    _instance: PhantomData<T>,
+}
+
+impl<T: Clone + FaceCenter> FaceInstances<T> {
+    // None when no section has a face
+    fn new(render_context: &RenderContext, sections: &[&[T]], is_sorted: bool) -> Option<Self> {
+        let raw_face_instances = sections.concat();
+        if raw_face_instances.is_empty() {
+            return None;
+        }
+
+        let mut section_instances = Vec::with_capacity(sections.len());
+        let mut start = 0;
+        for section in sections {
+            section_instances.push(start..start + section.len() as u32);
+            start += section.len() as u32;
+        }
+        let buffer = render_context
+            .device
+            .create_buffer_init(&BufferInitDescriptor {
+                label: Some("Chunk instances buffer init"),
+                contents: any_slice_as_u8_slice(&raw_face_instances),
+                usage: if is_sorted {
+                    BufferUsages::VERTEX | BufferUsages::COPY_DST
+                } else {
+                    BufferUsages::VERTEX
+                },
+            });
+        Some(Self {
+            buffer,
+            section_instances,
+            sorted_instances: is_sorted.then_some(raw_face_instances),
+            _instance: PhantomData,
+        })
+    }
+
+    // Orders the faces of every section from the farthest to the closest one, the camera position
+    // being from the chunk origin in blocks
+    fn sort(&mut self, render_context: &RenderContext, camera_position: &Point3<f32>) {
+        let Some(instances) = self.sorted_instances.as_mut() else {
+            return;
+        };
+        for section in self.section_instances.iter() {
+            sort_back_to_front(
+                &mut instances[section.start as usize..section.end as usize],
+                camera_position,
+            );
+        }
+        render_context
+            .queue
+            .write_buffer(&self.buffer, 0, any_slice_as_u8_slice(instances));
+    }
+
+    // The instances are bound from the first face to draw, a base instance is not supported
+    // everywhere
+    fn draw<'b>(&'b self, render_pass: &mut RenderPass<'b>, instances: &Range<u32>) {
+        let stride = std::mem::size_of::<T>() as wgpu::BufferAddress;
+        render_pass.set_vertex_buffer(
+            1,
+            self.buffer.slice(
+                instances.start as wgpu::BufferAddress * stride
+                    ..instances.end as wgpu::BufferAddress * stride,
+            ),
+        );
+        render_pass.draw_indexed(0..6, 0, 0..instances.len() as u32);
+    }
+}
+
+// Faces of the cubes and quads of the other models of a chunk drawn in the same pass
+struct LayerMesh {
+    faces: Option<FaceInstances<RawFaceInstance>>,
+    model_faces: Option<FaceInstances<RawModelFaceInstance>>,
+}
+
+impl LayerMesh {
+    // The faces of a sorted mesh can be reordered with sort
+    fn new(render_context: &RenderContext, sections: &[&SectionMesh], is_sorted: bool) -> Self {
+        Self {
+            faces: FaceInstances::new(
+                render_context,
+                &sections
+                    .iter()
+                    .map(|section| section.faces.as_slice())
+                    .collect::<Vec<_>>(),
+                is_sorted,
+            ),
+            model_faces: FaceInstances::new(
+                render_context,
+                &sections
+                    .iter()
+                    .map(|section| section.model_faces.as_slice())
+                    .collect::<Vec<_>>(),
+                is_sorted,
+            ),
+        }
+    }
+
+    fn sort(&mut self, render_context: &RenderContext, camera_position: &Point3<f32>) {
+        if let Some(faces) = self.faces.as_mut() {
+            faces.sort(render_context, camera_position);
+        }
+        if let Some(model_faces) = self.model_faces.as_mut() {
+            model_faces.sort(render_context, camera_position);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.faces.is_none() && self.model_faces.is_none()
+    }
+
+    // Faces then model quads of the section, empty without any
+    fn get_section_instances(&self, section_y: usize) -> (Range<u32>, Range<u32>) {
+        (
+            self.faces
+                .as_ref()
+                .map_or(0..0, |faces| faces.section_instances[section_y].clone()),
+            self.model_faces
+                .as_ref()
+                .map_or(0..0, |faces| faces.section_instances[section_y].clone()),
+        )
+    }
+}
+
+// Faces of a chunk on the GPU, with the chunk origin they are positioned from. The faces buffers
+// are replaced when the chunk is remeshed and everything is freed when it unloads.
+struct ChunkMesh {
+    // Opaque and cutout faces
+    mesh: LayerMesh,
+    translucent_mesh: LayerMesh,
+    // Block the camera was in when the translucent faces were last sorted, None until they are
+    translucent_sorted_from: Option<Point3<i32>>,
+    _origin_buffer: Buffer,
+    origin_bind_group: BindGroup,
+    aabb_min: Point3<f32>,
+    aabb_max: Point3<f32>,
+    // Opaque faces and quads of the visible sections, the adjacent ones merged into a single draw
+    draw_instances: Vec<Range<u32>>,
+    model_draw_instances: Vec<Range<u32>>,
+}
+
+// Shaders and layouts the pipelines are made of, kept to rebuild them
+struct BlockShaders {
+    pipeline_layout: PipelineLayout,
+    vertex_shader_module: ShaderModule,
+    model_vertex_shader_module: ShaderModule,
+    // Alpha tested for the cutout blocks, the translucent ones are blended without
+    fragment_shader_module: ShaderModule,
+    translucent_fragment_shader_module: ShaderModule,
+    block_instance_vertex_buffer_layout: [VertexBufferLayout<'static>; 2],
+    model_instance_vertex_buffer_layout: [VertexBufferLayout<'static>; 2],
+}
+
+// The faces of the cubes and the quads of the other models, opaque then translucent
+struct BlockPipelines {
+    faces: wgpu::RenderPipeline,
+    model_faces: wgpu::RenderPipeline,
+    translucent_faces: wgpu::RenderPipeline,
+    translucent_model_faces: wgpu::RenderPipeline,
+}
+
+impl BlockPipelines {
+    fn new(
+        render_context: &RenderContext,
+        shaders: &BlockShaders,
+        polygon_mode: PolygonMode,
+    ) -> Self {
+        let create = |vertex_shader_module, buffers: &[VertexBufferLayout], is_translucent| {
+            create_render_pipeline(
+                render_context,
+                &shaders.pipeline_layout,
+                vertex_shader_module,
+                if is_translucent {
+                    &shaders.translucent_fragment_shader_module
+                } else {
+                    &shaders.fragment_shader_module
+                },
+                buffers,
+                polygon_mode,
+                is_translucent,
+            )
+        };
+        let (faces, models) = (
+            &shaders.block_instance_vertex_buffer_layout,
+            &shaders.model_instance_vertex_buffer_layout,
+        );
+        Self {
+            faces: create(&shaders.vertex_shader_module, faces, false),
+            model_faces: create(&shaders.model_vertex_shader_module, models, false),
+            translucent_faces: create(&shaders.vertex_shader_module, faces, true),
+            translucent_model_faces: create(&shaders.model_vertex_shader_module, models, true),
+        }
+    }
+}
+
+// Chunks submitted and skipped by the frustum culling, and sections hidden by the visibility walk
+// on the last frame
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct RenderStats {
+    pub drawn_chunks: usize,
+    pub culled_chunks: usize,
+    pub drawn_sections: usize,
+    pub hidden_sections: usize,
+    pub drawn_faces: usize,
+}
+
 #[derive(Resource)]
 pub struct GameRenderer {
     camera_renderer: CameraRenderer,
     camera_bind_group: BindGroup,
     texture_bind_group: BindGroup,
+    chunk_bind_group_layout: BindGroupLayout,
     wireframe_only: bool,
-    render_pipeline: wgpu::RenderPipeline,
+    pipelines: BlockPipelines,
     // render_pipeline_descriptor: RenderPipelineDescriptor<'static>,
     cube_vertex_buffer: Buffer,
     cube_indices_buffer: Buffer,
-    face_instances_buffer: Buffer,
-    fragment_shader_module: ShaderModule,
-    vertex_shader_module: ShaderModule,
-    color_targets_state: [Option<ColorTargetState>; 1],
-    block_instance_vertex_buffer_layout: [VertexBufferLayout<'static>; 2],
-    cubes_pipeline_layout: PipelineLayout,
+    shaders: BlockShaders,
 
     texture_atlas: Texture,
 
-    faces_count: u32,
+    chunk_meshes: HashMap<ChunkPos, ChunkMesh>,
+    // Visible sections with translucent faces, from the farthest to the closest one
+    translucent_sections: Vec<(ChunkPos, usize)>,
 }
 
 impl GameRenderer {
-    pub fn new(render_context: &RenderContext, window: &Window, camera: &Camera) -> Self {
-        let camera_renderer = CameraRenderer::new(render_context, window, camera);
+    pub fn new(render_context: &RenderContext, camera: &Camera) -> Self {
+        let camera_renderer = CameraRenderer::new(render_context, camera);
 
         let camera_bind_group_layout =
             render_context
@@ -144,12 +361,33 @@ impl GameRenderer {
                 ],
             });
 
+        let chunk_bind_group_layout =
+            render_context
+                .device
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("Create chunk bind group layout: Bind group layout descriptor"),
+                    entries: &[BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                });
+
         let game_pipeline_layout =
             render_context
                 .device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     label: Some("Create pipeline layout"),
-                    bind_group_layouts: &[&camera_bind_group_layout, &texture_bind_group_layout],
+                    bind_group_layouts: &[
+                        &camera_bind_group_layout,
+                        &texture_bind_group_layout,
+                        &chunk_bind_group_layout,
+                    ],
                     push_constant_ranges: &[],
                 });
 
@@ -161,6 +399,14 @@ impl GameRenderer {
                     source: include_spirv!("./shaders/vertex.vert.spv").source,
                 });
 
+        let model_vertex_shader_module =
+            render_context
+                .device
+                .create_shader_module(ShaderModuleDescriptor {
+                    label: Some("Shader module model vert"),
+                    source: include_spirv!("./shaders/model.vert.spv").source,
+                });
+
         let fragment_shader_module =
             render_context
                 .device
@@ -169,6 +415,14 @@ impl GameRenderer {
                     source: include_spirv!("./shaders/fragment.frag.spv").source,
                 });
 
+        let translucent_fragment_shader_module =
+            render_context
+                .device
+                .create_shader_module(ShaderModuleDescriptor {
+                    label: Some("Shader module translucent frag"),
+                    source: include_spirv!("./shaders/translucent.frag.spv").source,
+                });
+
         let vertices = [
             // Front
             // 0, 1, 2 & 0, 2, 3
@@ -197,14 +451,6 @@ impl GameRenderer {
             0u16, 1, 2, 0, 2, 3, // Front
         ];
 
-        let block_instances_buffer =
-            render_context
-                .device
-                .create_buffer_init(&BufferInitDescriptor {
-                    label: Some("Block instances buffer init"),
-                    contents: any_sized_as_u8_slice(&()),
-                    usage: BufferUsages::VERTEX,
-                });
         let cubes_vertices_buffer =
             render_context
                 .device
@@ -222,119 +468,254 @@ impl GameRenderer {
                     usage: BufferUsages::INDEX,
                 });
 
-        let block_instance_vertex_buffer_layout = [
-            Vertex::vertex_buffer_layout(),
-            RawFaceInstance::vertex_buffer_layout(),
-        ];
-
-        let color_targets_state = [Some(ColorTargetState {
-            format: TextureFormat::Bgra8UnormSrgb,
-            blend: Some(BlendState {
-                color: BlendComponent::REPLACE,
-                alpha: BlendComponent::REPLACE,
-            }),
-            write_mask: ColorWrites::all(),
-        })];
-
-        let render_pipeline_descriptor = RenderPipelineDescriptor {
-            label: Some("Create render pipeline: Render pipeline descriptor"),
-            layout: Some(&game_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vertex_shader_module,
-                entry_point: "main",
-                buffers: &block_instance_vertex_buffer_layout,
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
-            // depth_stencil: None,
-            depth_stencil: Some(DepthStencilState {
-                format: Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: CompareFunction::Less,
-                stencil: Default::default(),
-                bias: Default::default(),
-            }),
-            multisample: MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(FragmentState {
-                module: &fragment_shader_module,
-                entry_point: "main",
-                targets: &color_targets_state,
-            }),
-            multiview: None,
+        let shaders = BlockShaders {
+            pipeline_layout: game_pipeline_layout,
+            vertex_shader_module,
+            model_vertex_shader_module,
+            fragment_shader_module,
+            translucent_fragment_shader_module,
+            block_instance_vertex_buffer_layout: [
+                Vertex::vertex_buffer_layout(),
+                RawFaceInstance::vertex_buffer_layout(),
+            ],
+            model_instance_vertex_buffer_layout: [
+                Vertex::vertex_buffer_layout(),
+                RawModelFaceInstance::vertex_buffer_layout(),
+            ],
         };
-
-        let render_pipeline = render_context
-            .device
-            .create_render_pipeline(&render_pipeline_descriptor);
+        let pipelines = BlockPipelines::new(render_context, &shaders, PolygonMode::Fill);
 
         Self {
             camera_renderer,
             camera_bind_group,
-            wireframe_only: render_pipeline_descriptor.primitive.polygon_mode != PolygonMode::Fill,
-            cubes_pipeline_layout: game_pipeline_layout,
-            render_pipeline,
+            wireframe_only: false,
+            pipelines,
 
             cube_vertex_buffer: cubes_vertices_buffer,
             cube_indices_buffer: cubes_indices_buffer,
-            face_instances_buffer: block_instances_buffer,
 
-            fragment_shader_module,
-            vertex_shader_module,
-
-            color_targets_state,
-            block_instance_vertex_buffer_layout, // render_pipeline_descriptor,
-            faces_count: 0,
+            shaders,
+            chunk_meshes: HashMap::new(),
+            translucent_sections: Vec::new(),
             texture_atlas,
             texture_bind_group,
+            chunk_bind_group_layout,
         }
     }
 
-    pub fn update_blocks(
+    // Uploads the faces of a chunk that was just meshed, without touching the other chunks
+    pub fn update_chunk(
         &mut self,
         render_context: &RenderContext,
-        raw_face_instances: &[RawFaceInstance],
-        faces_count: u32,
+        chunk_coord: ChunkPos,
+        origin: &Vector3<f32>,
+        sections: &[&SectionMesh],
+        translucent_sections: &[&SectionMesh],
     ) {
-        self.faces_count = faces_count;
-        self.face_instances_buffer =
-            render_context
-                .device
-                .create_buffer_init(&BufferInitDescriptor {
-                    label: Some("Block instances buffer recreation"),
-                    contents: any_slice_as_u8_slice(raw_face_instances),
-                    usage: BufferUsages::VERTEX,
-                })
+        self.translucent_sections
+            .retain(|(coord, _)| *coord != chunk_coord);
+        // Filled by the next cull_chunks
+        let draw_instances = Vec::new();
+        let model_draw_instances = Vec::new();
+
+        let mesh = LayerMesh::new(render_context, sections, false);
+        let translucent_mesh = LayerMesh::new(render_context, translucent_sections, true);
+        if mesh.is_empty() && translucent_mesh.is_empty() {
+            self.chunk_meshes.remove(&chunk_coord);
+            return;
+        }
+
+        if let Some(chunk_mesh) = self.chunk_meshes.get_mut(&chunk_coord) {
+            chunk_mesh.mesh = mesh;
+            chunk_mesh.translucent_mesh = translucent_mesh;
+            chunk_mesh.translucent_sorted_from = None;
+            chunk_mesh.draw_instances = draw_instances;
+            chunk_mesh.model_draw_instances = model_draw_instances;
+            return;
+        }
+
+        let origin_buffer = render_context
+            .device
+            .create_buffer_init(&BufferInitDescriptor {
+                label: Some("Chunk origin buffer init"),
+                contents: any_sized_as_u8_slice(&Vector4::new(origin.x, origin.y, origin.z, 0.0)),
+                usage: BufferUsages::UNIFORM,
+            });
+        let origin_bind_group = render_context
+            .device
+            .create_bind_group(&BindGroupDescriptor {
+                label: Some("Create chunk bind group"),
+                layout: &self.chunk_bind_group_layout,
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: origin_buffer.as_entire_binding(),
+                }],
+            });
+
+        let aabb_min = Point3::from(*origin);
+        let aabb_max = aabb_min
+            + Vector3::new(
+                Chunk::CHUNK_SIDE_SIZE,
+                Chunk::CHUNK_VERTICAL_BLOCK as f32 * Block::SIZE,
+                Chunk::CHUNK_SIDE_SIZE,
+            );
+        self.chunk_meshes.insert(
+            chunk_coord,
+            ChunkMesh {
+                mesh,
+                translucent_mesh,
+                translucent_sorted_from: None,
+                _origin_buffer: origin_buffer,
+                origin_bind_group,
+                aabb_min,
+                aabb_max,
+                draw_instances,
+                model_draw_instances,
+            },
+        );
     }
-    pub fn prerender(&self, render_context: &RenderContext, window: &Window, camera: &Camera) {
-        self.camera_renderer.update(render_context, window, camera);
+
+    pub fn remove_chunk(&mut self, chunk_coord: &ChunkPos) {
+        self.chunk_meshes.remove(chunk_coord);
+        self.translucent_sections
+            .retain(|(coord, _)| coord != chunk_coord);
     }
 
-    pub fn render<'b>(&'b self, render_pass: &mut RenderPass<'b>) {
-        render_pass.set_pipeline(&self.render_pipeline);
+    // Only the chunks inside the frustum are drawn until the next call, and of those only the
+    // visible sections when they are known. The translucent sections are sorted from the camera
+    // position, and the faces inside them again whenever the camera moves to another block.
+    pub fn cull_chunks(
+        &mut self,
+        render_context: &RenderContext,
+        frustum: &Frustum,
+        camera_position: &Point3<f32>,
+        visible_sections: Option<&HashSet<SectionPos>>,
+    ) -> RenderStats {
+        let mut render_stats = RenderStats::default();
+        let mut translucent_sections = Vec::new();
+        let camera_block = camera_position.map(|coord| (coord / Block::SIZE).floor() as i32);
+        for (chunk_coord, chunk_mesh) in self.chunk_meshes.iter_mut() {
+            chunk_mesh.draw_instances.clear();
+            chunk_mesh.model_draw_instances.clear();
+            if !frustum.contains_aabb(&chunk_mesh.aabb_min, &chunk_mesh.aabb_max) {
+                render_stats.culled_chunks += 1;
+                continue;
+            }
+
+            if chunk_mesh.translucent_sorted_from != Some(camera_block) {
+                let from_origin = (camera_position - chunk_mesh.aabb_min) / Block::SIZE;
+                chunk_mesh
+                    .translucent_mesh
+                    .sort(render_context, &Point3::from(from_origin));
+                chunk_mesh.translucent_sorted_from = Some(camera_block);
+            }
+
+            let mut is_drawn = false;
+            for section_y in 0..Chunk::SECTION_COUNT {
+                let (instances, model_instances) = chunk_mesh.mesh.get_section_instances(section_y);
+                let (translucent_instances, translucent_model_instances) =
+                    chunk_mesh.translucent_mesh.get_section_instances(section_y);
+                let is_translucent =
+                    !translucent_instances.is_empty() || !translucent_model_instances.is_empty();
+                if instances.is_empty() && model_instances.is_empty() && !is_translucent {
+                    continue;
+                }
+                let section_pos = Vector3::new(chunk_coord.x, section_y as i32, chunk_coord.z);
+                if visible_sections.is_some_and(|sections| !sections.contains(&section_pos)) {
+                    render_stats.hidden_sections += 1;
+                    continue;
+                }
+
+                is_drawn = true;
+                render_stats.drawn_sections += 1;
+                render_stats.drawn_faces += instances.len()
+                    + model_instances.len()
+                    + translucent_instances.len()
+                    + translucent_model_instances.len();
+                if is_translucent {
+                    let (min, max) = get_section_bounds(&section_pos);
+                    let distance =
+                        nalgebra::distance_squared(&nalgebra::center(&min, &max), camera_position);
+                    translucent_sections.push((distance, *chunk_coord, section_y));
+                }
+                push_draw(&mut chunk_mesh.draw_instances, instances);
+                push_draw(&mut chunk_mesh.model_draw_instances, model_instances);
+            }
+            if is_drawn {
+                render_stats.drawn_chunks += 1;
+            } else {
+                render_stats.culled_chunks += 1;
+            }
+        }
 
+        translucent_sections.sort_by(|(a, ..), (b, ..)| b.total_cmp(a));
+        self.translucent_sections = translucent_sections
+            .into_iter()
+            .map(|(_, chunk_coord, section_y)| (chunk_coord, section_y))
+            .collect();
+        render_stats
+    }
+
+    pub fn prerender(&self, render_context: &RenderContext, camera: &Camera) {
+        self.camera_renderer.update(render_context, camera);
+    }
+
+    pub fn render<'b>(&'b self, render_pass: &mut RenderPass<'b>) {
         render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
         render_pass.set_bind_group(1, &self.texture_bind_group, &[]);
 
         render_pass.set_vertex_buffer(0, self.cube_vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, self.face_instances_buffer.slice(..));
 
         render_pass.set_index_buffer(
             self.cube_indices_buffer.slice(..),
             wgpu::IndexFormat::Uint16,
         );
 
-        render_pass.draw_indexed(0..6, 0, 0..self.faces_count);
+        render_pass.set_pipeline(&self.pipelines.faces);
+        for chunk_mesh in self.chunk_meshes.values() {
+            let Some(faces) = &chunk_mesh.mesh.faces else {
+                continue;
+            };
+            render_pass.set_bind_group(2, &chunk_mesh.origin_bind_group, &[]);
+            for instances in chunk_mesh.draw_instances.iter() {
+                faces.draw(render_pass, instances);
+            }
+        }
+        render_pass.set_pipeline(&self.pipelines.model_faces);
+        for chunk_mesh in self.chunk_meshes.values() {
+            let Some(model_faces) = &chunk_mesh.mesh.model_faces else {
+                continue;
+            };
+            render_pass.set_bind_group(2, &chunk_mesh.origin_bind_group, &[]);
+            for instances in chunk_mesh.model_draw_instances.iter() {
+                model_faces.draw(render_pass, instances);
+            }
+        }
+
+        // Blended over the opaque faces, the farthest first. Inside a section the faces of the
+        // cubes are drawn before the model quads, each sorted on their own.
+        for (chunk_coord, section_y) in self.translucent_sections.iter() {
+            let chunk_mesh = &self.chunk_meshes[chunk_coord];
+            let translucent_mesh = &chunk_mesh.translucent_mesh;
+            let (instances, model_instances) = translucent_mesh.get_section_instances(*section_y);
+            render_pass.set_bind_group(2, &chunk_mesh.origin_bind_group, &[]);
+            if let Some(faces) = translucent_mesh
+                .faces
+                .as_ref()
+                .filter(|_| !instances.is_empty())
+            {
+                render_pass.set_pipeline(&self.pipelines.translucent_faces);
+                faces.draw(render_pass, &instances);
+            }
+            if let Some(model_faces) = translucent_mesh
+                .model_faces
+                .as_ref()
+                .filter(|_| !model_instances.is_empty())
+            {
+                render_pass.set_pipeline(&self.pipelines.translucent_model_faces);
+                model_faces.draw(render_pass, &model_instances);
+            }
+        }
     }
 
     pub fn is_wireframe_only(&self) -> bool {
@@ -353,44 +734,119 @@ impl GameRenderer {
         } else {
             PolygonMode::Fill
         };
-        self.render_pipeline =
-            render_context
-                .device
-                .create_render_pipeline(&RenderPipelineDescriptor {
-                    label: Some("Change render pipeline: Render pipeline descriptor"),
-                    layout: Some(&self.cubes_pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &self.vertex_shader_module,
-                        entry_point: "main",
-                        buffers: &self.block_instance_vertex_buffer_layout,
-                    },
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        strip_index_format: None,
-                        front_face: FrontFace::Ccw,
-                        cull_mode: Some(Face::Back),
-                        unclipped_depth: false,
-                        polygon_mode,
-                        conservative: false,
-                    },
-                    depth_stencil: Some(DepthStencilState {
-                        format: Texture::DEPTH_FORMAT,
-                        depth_write_enabled: true,
-                        depth_compare: CompareFunction::Less,
-                        stencil: Default::default(),
-                        bias: Default::default(),
-                    }),
-                    multisample: MultisampleState {
-                        count: 1,
-                        mask: !0,
-                        alpha_to_coverage_enabled: false,
-                    },
-                    fragment: Some(FragmentState {
-                        module: &self.fragment_shader_module,
-                        entry_point: "main",
-                        targets: &self.color_targets_state,
-                    }),
-                    multiview: None,
-                });
+        self.pipelines = BlockPipelines::new(render_context, &self.shaders, polygon_mode);
+    }
+}
+
+// Sorts by the distance from the camera to the middle of the faces
+fn sort_back_to_front<T: FaceCenter>(faces: &mut [T], camera_position: &Point3<f32>) {
+    let distance =
+        |face: &T| nalgebra::distance_squared(&face.get_center().into(), camera_position);
+    faces.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+}
+
+// Adds the instances of a section to the draws, merged with the previous section when adjacent
+fn push_draw(draw_instances: &mut Vec<Range<u32>>, instances: Range<u32>) {
+    if instances.is_empty() {
+        return;
+    }
+    match draw_instances.last_mut() {
+        Some(last) if last.end == instances.start => last.end = instances.end,
+        _ => draw_instances.push(instances),
+    }
+}
+
+// Opaque and cutout faces replace what is behind them and write the depth. Translucent faces are
+// blended over them and do not write the depth, so they do not hide each other.
+fn create_render_pipeline(
+    render_context: &RenderContext,
+    layout: &PipelineLayout,
+    vertex_shader_module: &ShaderModule,
+    fragment_shader_module: &ShaderModule,
+    buffers: &[VertexBufferLayout],
+    polygon_mode: PolygonMode,
+    is_translucent: bool,
+) -> wgpu::RenderPipeline {
+    let color_targets_state = [Some(if is_translucent {
+        ColorTargetState {
+            format: TextureFormat::Bgra8UnormSrgb,
+            blend: Some(BlendState::ALPHA_BLENDING),
+            write_mask: ColorWrites::all(),
+        }
+    } else {
+        // The alpha of the cleared target is kept
+        ColorTargetState {
+            format: TextureFormat::Bgra8UnormSrgb,
+            blend: Some(BlendState {
+                color: BlendComponent::REPLACE,
+                alpha: BlendComponent::REPLACE,
+            }),
+            write_mask: ColorWrites::COLOR,
+        }
+    })];
+
+    render_context
+        .device
+        .create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Create render pipeline: Render pipeline descriptor"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: vertex_shader_module,
+                entry_point: "main",
+                buffers,
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: !is_translucent,
+                depth_compare: CompareFunction::Less,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(FragmentState {
+                module: fragment_shader_module,
+                entry_point: "main",
+                targets: &color_targets_state,
+            }),
+            multiview: None,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::sort_back_to_front;
+    use crate::boxworld::block::{BlockFace, RawFaceInstance};
+    use nalgebra::{Point3, Vector2, Vector3};
+
+    fn face(position: Vector3<usize>, face: BlockFace) -> RawFaceInstance {
+        RawFaceInstance::new(position, face, Vector2::new(1, 1), Vector2::new(0, 0), 0, 0)
+    }
+
+    #[test]
+    fn faces_of_a_section_are_drawn_from_the_farthest() {
+        // Water behind a glass pane, both in the same section, seen from the front
+        let glass = face(Vector3::new(4, 2, 6), BlockFace::FRONT);
+        let water = face(Vector3::new(4, 2, 3), BlockFace::FRONT);
+        let water_top = face(Vector3::new(4, 2, 3), BlockFace::TOP);
+        let mut faces = [glass, water_top, water];
+
+        sort_back_to_front(&mut faces, &Point3::new(4.5, 2.5, 12.0));
+        assert_eq!(faces, [water_top, water, glass]);
+        // From behind
+        sort_back_to_front(&mut faces, &Point3::new(4.5, 2.5, -5.0));
+        assert_eq!(faces, [glass, water, water_top]);
     }
 }
@@ -1,27 +1,29 @@
 use crate::game::camera::Camera;
-use crate::misc::window::Window;
-use crate::ui::{UIDrawData};
+use crate::ui::UIDrawData;
 
 use crate::renderer::context::RenderContext;
 use crate::renderer::game_renderer::GameRenderer;
 
 use bevy_ecs::prelude::*;
 
+use image::RgbaImage;
 use wgpu::{
     Color, LoadOp, Operations, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
-    RenderPassDescriptor,
+    RenderPassDescriptor, TextureView,
 };
 
-
 use crate::renderer::texture::Texture;
 use crate::ui::renderer::DebugUIRenderer;
 use winit::dpi::PhysicalSize;
+use winit::window::Window;
 
 pub mod camera;
 pub mod context;
 pub mod error;
+pub mod frustum;
 pub mod game_renderer;
 pub mod plugins;
+pub mod screenshot;
 pub mod systems;
 pub mod texture;
 pub mod util;
@@ -37,6 +39,15 @@ pub struct Renderer {
 impl Renderer {
     pub async fn new(window: &Window) -> Self {
         let render_context = RenderContext::new(window).await;
+        Self::from_render_context(render_context)
+    }
+
+    pub async fn new_offscreen(size: PhysicalSize<u32>) -> Self {
+        let render_context = RenderContext::new_offscreen(size).await;
+        Self::from_render_context(render_context)
+    }
+
+    fn from_render_context(render_context: RenderContext) -> Self {
         let ui_renderer = DebugUIRenderer::new(&render_context);
         let depth_texture = Texture::new_depth(&render_context);
 
@@ -50,20 +61,76 @@ impl Renderer {
     pub fn render(
         &mut self,
         camera: &Camera,
-        window: &Window,
         ui_render_state: UIDrawData,
         game_renderer: &GameRenderer,
     ) {
-        game_renderer.prerender(&self.render_context, window, camera);
+        let (texture, texture_view) = self.render_context.get_current_texture();
+
+        self.render_to(
+            &[&texture_view],
+            camera,
+            Some(ui_render_state),
+            game_renderer,
+        );
+
+        texture.present();
+    }
 
-        let (mut command_encoder, texture, texture_view) =
-            self.render_context.create_command_encoder();
+    // Presents the frame and keeps an image of it, drawn to both in the same pass
+    pub fn render_and_capture(
+        &mut self,
+        camera: &Camera,
+        ui_render_state: UIDrawData,
+        game_renderer: &GameRenderer,
+    ) -> RgbaImage {
+        let (texture, texture_view) = self.render_context.get_current_texture();
+        let render_target = Texture::new_render_target(&self.render_context);
+
+        self.render_to(
+            &[&texture_view, &render_target.texture_view],
+            camera,
+            Some(ui_render_state),
+            game_renderer,
+        );
 
-        {
+        texture.present();
+        screenshot::read_texture(&self.render_context, &render_target)
+    }
+
+    pub fn capture(
+        &mut self,
+        camera: &Camera,
+        ui_render_state: Option<UIDrawData>,
+        game_renderer: &GameRenderer,
+    ) -> RgbaImage {
+        let render_target = Texture::new_render_target(&self.render_context);
+
+        self.render_to(
+            &[&render_target.texture_view],
+            camera,
+            ui_render_state,
+            game_renderer,
+        );
+
+        screenshot::read_texture(&self.render_context, &render_target)
+    }
+
+    fn render_to(
+        &mut self,
+        texture_views: &[&TextureView],
+        camera: &Camera,
+        ui_render_state: Option<UIDrawData>,
+        game_renderer: &GameRenderer,
+    ) {
+        game_renderer.prerender(&self.render_context, camera);
+
+        let mut command_encoder = self.render_context.create_command_encoder();
+
+        for texture_view in texture_views {
             let mut render_pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
                 label: Some("Render pass descriptor"),
                 color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &texture_view,
+                    view: texture_view,
                     resolve_target: None,
                     ops: Operations {
                         store: true,
@@ -87,20 +154,23 @@ impl Renderer {
 
             game_renderer.render(&mut render_pass);
         }
-        self.ui_renderer.render(
-            &mut command_encoder,
-            &self.render_context,
-            &texture_view,
-            ui_render_state,
-        );
+        let has_ui = ui_render_state.is_some();
+        if let Some(ui_render_state) = ui_render_state {
+            self.ui_renderer.render(
+                &mut command_encoder,
+                &self.render_context,
+                texture_views,
+                ui_render_state,
+            );
+        }
 
         self.render_context
             .queue
             .submit(core::iter::once(command_encoder.finish()));
 
-        self.ui_renderer.post_render();
-
-        texture.present();
+        if has_ui {
+            self.ui_renderer.post_render();
+        }
     }
 
     pub fn resize(&mut self, new_window_size: &PhysicalSize<u32>) {
@@ -1,11 +1,86 @@
-use crate::plugin::Plugin;
+use crate::app::error::AppError;
+use crate::app::input::plugin::InputPlugin;
+use crate::app::input::InputManager;
+use crate::boxworld::plugin::WorldPlugin;
+use crate::boxworld::BoxWorld;
+use crate::game::camera::{Camera, CameraPlugin};
+use crate::game::schedule::ScheduleStage;
+use crate::plugin::{Plugin, RequiredResource};
+use crate::renderer::game_renderer::{GameRenderer, RenderStats};
+use crate::renderer::systems::blocks::update_blocks;
+use crate::renderer::systems::culling::cull_chunks;
 use crate::renderer::systems::wireframe::update_switch_wireframe;
-use bevy_ecs::prelude::{Schedule, World};
+use crate::renderer::Renderer;
+use bevy_ecs::prelude::*;
+use futures_lite::future;
+use winit::dpi::PhysicalSize;
+use winit::window::Window;
 
-pub struct RendererPlugin;
+pub struct RendererPlugin {
+    // Size of the render target when there is no window to present to
+    offscreen_size: PhysicalSize<u32>,
+}
+
+impl RendererPlugin {
+    pub const LABEL: &'static str = "renderer";
+
+    pub fn offscreen(size: PhysicalSize<u32>) -> Self {
+        Self {
+            offscreen_size: size,
+        }
+    }
+}
+
+impl Default for RendererPlugin {
+    fn default() -> Self {
+        Self::offscreen(PhysicalSize::new(1024, 768))
+    }
+}
 
 impl Plugin for RendererPlugin {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[InputPlugin::LABEL, CameraPlugin::LABEL, WorldPlugin::LABEL]
+    }
+
+    fn required_resources(&self) -> Vec<RequiredResource> {
+        vec![
+            RequiredResource::of::<InputManager>(),
+            RequiredResource::of::<Camera>(),
+            RequiredResource::of::<BoxWorld>(),
+        ]
+    }
+
+    fn register_init(
+        &self,
+        world: &mut World,
+        _schedule: &mut Schedule,
+        window: Option<&Window>,
+    ) -> Result<(), AppError> {
+        let renderer = match window {
+            Some(window) => future::block_on(Renderer::new(window)),
+            None => future::block_on(Renderer::new_offscreen(self.offscreen_size)),
+        };
+        let camera = world.get_resource::<Camera>().unwrap();
+        let game_renderer = GameRenderer::new(&renderer.render_context, camera);
+        world.insert_resource(game_renderer);
+        world.insert_resource(renderer);
+        world.insert_resource(RenderStats::default());
+        Ok(())
+    }
+
     fn register_runtime(&self, _world: &mut World, schedule: &mut Schedule) {
-        schedule.add_systems(update_switch_wireframe);
+        schedule.add_systems(update_switch_wireframe.in_set(ScheduleStage::Update));
+    }
+
+    fn register_frame(&self, _world: &mut World, schedule: &mut Schedule) {
+        schedule.add_systems(
+            (update_blocks, cull_chunks)
+                .chain()
+                .in_set(ScheduleStage::PreRender),
+        );
     }
 }
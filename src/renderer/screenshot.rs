@@ -0,0 +1,90 @@
+use crate::renderer::context::RenderContext;
+use crate::renderer::error::RendererError;
+use crate::renderer::texture::Texture;
+use image::RgbaImage;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use wgpu::{
+    BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout,
+    Maintain, MapMode, Origin3d, TextureAspect, TextureFormat,
+};
+
+pub fn read_texture(render_context: &RenderContext, texture: &Texture) -> RgbaImage {
+    let width = render_context.render_surface_config.width;
+    let height = render_context.render_surface_config.height;
+
+    // Rows of a texture-to-buffer copy have to be aligned, so the padding is stripped afterwards
+    let unpadded_bytes_per_row = width * 4;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = render_context.device.create_buffer(&BufferDescriptor {
+        label: Some("Screenshot buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder = render_context.create_command_encoder();
+    command_encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture: &texture.texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: NonZeroU32::new(height),
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    render_context
+        .queue
+        .submit(core::iter::once(command_encoder.finish()));
+
+    let buffer_slice = buffer.slice(..);
+    buffer_slice.map_async(MapMode::Read, |result| result.unwrap());
+    render_context.device.poll(Maintain::Wait);
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let mapped = buffer_slice.get_mapped_range();
+        for row in mapped.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    buffer.unmap();
+
+    if matches!(
+        render_context.render_surface_config.format,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+    ) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    RgbaImage::from_raw(width, height, pixels).unwrap()
+}
+
+pub fn save(image: &RgbaImage, path: &Path) -> Result<(), RendererError> {
+    image.save(path).map_err(|_| RendererError::IOError)
+}
+
+pub fn default_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    PathBuf::from(format!("screenshot-{}.png", timestamp))
+}
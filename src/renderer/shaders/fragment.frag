@@ -4,6 +4,7 @@ layout(location = 0) in vec3 vertexPos;
 layout(location = 1) in vec3 vertexNormal;
 layout(location = 2) in vec2 vertexTextureCoord;
 layout(location = 3) in flat ivec2 texturePos;
+layout(location = 4) in float vertexLight;
 
 layout(std140, set = 0, binding = 0) uniform Camera {
     mat4 projection;
@@ -41,9 +42,14 @@ void main()
     vec3 specularColor = specularStrength * specular * lightColor;
 
     vec2 textureAtlasSlotSize = 1. / vec2(textureAtlasSize);
-    vec2 textureCoord = mix(textureAtlasSlotSize * texturePos, textureAtlasSlotSize * vec2(texturePos + 1), vertexTextureCoord);
-    vec3 col = texture(sampler2D(textureAtlas, textureAtlasSampler), textureCoord).rgb;
-    //vec3 resultColor = (ambientColor + diffuseColor + specularColor) * col;
+    vec2 tileCoord = fract(vertexTextureCoord);
+    vec2 textureCoord = mix(textureAtlasSlotSize * texturePos, textureAtlasSlotSize * vec2(texturePos + 1), tileCoord);
+    vec4 col = texture(sampler2D(textureAtlas, textureAtlasSampler), textureCoord);
+    // Holes of the cutout blocks
+    if (col.a < .5) {
+        discard;
+    }
+    //vec3 resultColor = (ambientColor + diffuseColor + specularColor) * col.rgb;
     //fragColor = vec4(resultColor, 1.);
-    fragColor = vec4(col, 1.);
+    fragColor = vec4(col.rgb * vertexLight, col.a);
 }
@@ -0,0 +1,54 @@
+#version 450 core
+
+layout(location = 0) in vec3 vertexPos;
+layout(location = 1) in vec3 vertexNormal;
+layout(location = 2) in vec2 vertexTextureCoord;
+// Instance, packed as described on RawModelFaceInstance
+layout(location = 10) in uvec4 face;
+
+layout(std140, set = 0, binding = 0) uniform Camera {
+    mat4 projection;
+    mat4 view;
+    vec3 cameraPos;
+};
+
+layout(std140, set = 2, binding = 0) uniform Chunk {
+    vec3 chunkOrigin;
+};
+
+layout(location = 0) out vec3 vertexPosOut;
+layout(location = 1) out vec3 vertexNormalOut;
+layout(location = 2) out vec2 vertexTextureCoordOut;
+layout(location = 3) out flat ivec2 texturePosOut;
+layout(location = 4) out float vertexLightOut;
+
+// Models are laid out in sixteenths of a block
+const float modelSize = 16.;
+
+vec3 unpackVector(uint word)
+{
+    return vec3(ivec3(word & 0x3Fu, (word >> 6) & 0x3Fu, (word >> 12) & 0x3Fu) - 16);
+}
+
+void main()
+{
+    vec3 blockPos = vec3(face.x & 0xFu, (face.x >> 4) & 0x7Fu, (face.x >> 11) & 0xFu);
+    float light = float((face.x >> 15) & 0xFu) / 15.;
+    vec3 origin = vec3(face.y & 0x1Fu, (face.y >> 5) & 0x1Fu, (face.y >> 10) & 0x1Fu);
+    vec3 right = unpackVector(face.z);
+    vec3 up = unpackVector(face.w);
+    vec2 uvMin = vec2((face.z >> 18) & 0x1Fu, (face.z >> 23) & 0x1Fu);
+    vec2 uvMax = vec2((face.w >> 18) & 0x1Fu, (face.w >> 23) & 0x1Fu);
+
+    // The unit quad spans [-.5, .5] and starts at the origin of the face
+    vec3 modelPos = origin + (vertexPos.x + .5) * right + (vertexPos.y + .5) * up;
+    vec3 worldPos = chunkOrigin + blockPos + modelPos / modelSize;
+
+    vertexNormalOut = normalize(cross(right, up));
+    vertexPosOut = worldPos;
+    // Oriented like the faces of the cubes
+    vertexTextureCoordOut = mix(uvMin, uvMax, vertexTextureCoord) / modelSize;
+    texturePosOut = ivec2((face.y >> 15) & 0xFFu, (face.y >> 23) & 0xFFu);
+    vertexLightOut = light;
+    gl_Position = projection * view * vec4(worldPos, 1.0);
+}
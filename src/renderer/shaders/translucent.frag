@@ -0,0 +1,26 @@
+#version 450 core
+
+// Same as fragment.frag without the alpha test, the faces are blended whatever their alpha
+
+layout(location = 0) in vec3 vertexPos;
+layout(location = 1) in vec3 vertexNormal;
+layout(location = 2) in vec2 vertexTextureCoord;
+layout(location = 3) in flat ivec2 texturePos;
+layout(location = 4) in float vertexLight;
+
+layout(set = 1, binding = 0) uniform texture2D textureAtlas;
+layout(set = 1, binding = 1) uniform sampler textureAtlasSampler;
+layout(std140, set = 1, binding = 2) uniform TextureAtlas {
+    ivec2 textureAtlasSize;
+};
+
+layout(location = 0) out vec4 fragColor;
+
+void main()
+{
+    vec2 textureAtlasSlotSize = 1. / vec2(textureAtlasSize);
+    vec2 tileCoord = fract(vertexTextureCoord);
+    vec2 textureCoord = mix(textureAtlasSlotSize * texturePos, textureAtlasSlotSize * vec2(texturePos + 1), tileCoord);
+    vec4 col = texture(sampler2D(textureAtlas, textureAtlasSampler), textureCoord);
+    fragColor = vec4(col.rgb * vertexLight, col.a);
+}
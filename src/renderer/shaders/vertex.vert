@@ -3,12 +3,8 @@
 layout(location = 0) in vec3 vertexPos;
 layout(location = 1) in vec3 vertexNormal;
 layout(location = 2) in vec2 vertexTextureCoord;
-// Instance
-layout(location = 10) in vec4 model1;
-layout(location = 11) in vec4 model2;
-layout(location = 12) in vec4 model3;
-layout(location = 13) in vec4 model4;
-layout(location = 14) in ivec2 texturePos;
+// Instance, packed as described on RawFaceInstance
+layout(location = 10) in uvec2 face;
 
 layout(std140, set = 0, binding = 0) uniform Camera {
     mat4 projection;
@@ -16,22 +12,69 @@ layout(std140, set = 0, binding = 0) uniform Camera {
     vec3 cameraPos;
 };
 
+layout(std140, set = 2, binding = 0) uniform Chunk {
+    vec3 chunkOrigin;
+};
+
 layout(location = 0) out vec3 vertexPosOut;
 layout(location = 1) out vec3 vertexNormalOut;
 layout(location = 2) out vec2 vertexTextureCoordOut;
 layout(location = 3) out flat ivec2 texturePosOut;
+layout(location = 4) out float vertexLightOut;
+
+// Where the x and y axes of the unit quad and its normal point, for the faces in BlockFace order:
+// front, back, right, left, top, bottom
+const vec3 faceRight[6] = vec3[6](
+    vec3(1., 0., 0.),
+    vec3(-1., 0., 0.),
+    vec3(0., 0., -1.),
+    vec3(0., 0., 1.),
+    vec3(1., 0., 0.),
+    vec3(1., 0., 0.)
+);
+const vec3 faceUp[6] = vec3[6](
+    vec3(0., 1., 0.),
+    vec3(0., 1., 0.),
+    vec3(0., 1., 0.),
+    vec3(0., 1., 0.),
+    vec3(0., 0., -1.),
+    vec3(0., 0., 1.)
+);
+const vec3 faceNormal[6] = vec3[6](
+    vec3(0., 0., 1.),
+    vec3(0., 0., -1.),
+    vec3(1., 0., 0.),
+    vec3(-1., 0., 0.),
+    vec3(0., 1., 0.),
+    vec3(0., -1., 0.)
+);
 
 void main()
 {
-    mat4 model = mat4(
-        model1,
-        model2,
-        model3,
-        model4
-    );
-    vertexNormalOut = vertexNormal;
-    vertexPosOut = vertexPos;
-    vertexTextureCoordOut = vertexTextureCoord;
-    texturePosOut = texturePos;
-    gl_Position = projection * view * model * vec4(vertexPosOut, 1.0);
+    vec3 blockPos = vec3(face.x & 0xFu, (face.x >> 4) & 0x7Fu, (face.x >> 11) & 0xFu);
+    uint direction = (face.x >> 15) & 0x7u;
+    vec2 quadSize = vec2(((face.x >> 18) & 0xFu) + 1u, ((face.x >> 22) & 0xFu) + 1u);
+    float light = float((face.x >> 26) & 0xFu) / 15.;
+    uint textureTurns = (face.y >> 16) & 0x3u;
+
+    vec3 right = faceRight[direction];
+    vec3 up = faceUp[direction];
+    vec3 normal = faceNormal[direction];
+
+    // The quad starts at the lowest block and lies on the outer side of the blocks
+    vec3 center = blockPos + .5 * quadSize.x * abs(right) + .5 * quadSize.y * abs(up) + max(normal, 0.);
+    vec3 worldPos = chunkOrigin + center + vertexPos.x * quadSize.x * right + vertexPos.y * quadSize.y * up;
+
+    vertexNormalOut = normal;
+    vertexPosOut = worldPos;
+    // Merged quads repeat the texture once per block
+    vec2 textureCoord = vertexTextureCoord * quadSize;
+    // A quarter turn sends the right of the texture up the face
+    for (uint turn = 0u; turn < textureTurns; turn++) {
+        textureCoord = vec2(textureCoord.y, -textureCoord.x);
+    }
+    vertexTextureCoordOut = textureCoord;
+    texturePosOut = ivec2(face.y & 0xFFu, (face.y >> 8) & 0xFFu);
+    vertexLightOut = light;
+    gl_Position = projection * view * vec4(worldPos, 1.0);
 }
@@ -0,0 +1,14 @@
+use crate::boxworld::BoxWorld;
+use crate::renderer::game_renderer::GameRenderer;
+use crate::renderer::Renderer;
+use bevy_ecs::prelude::*;
+
+pub fn update_blocks(
+    mut box_world: ResMut<BoxWorld>,
+    renderer: Res<Renderer>,
+    mut game_renderer: ResMut<GameRenderer>,
+) {
+    if box_world.is_dirty() {
+        box_world.update_blocks(&renderer, &mut game_renderer);
+    }
+}
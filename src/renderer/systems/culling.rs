@@ -0,0 +1,27 @@
+use crate::boxworld::visibility::get_section_bounds;
+use crate::boxworld::BoxWorld;
+use crate::game::camera::Camera;
+use crate::renderer::frustum::Frustum;
+use crate::renderer::game_renderer::{GameRenderer, RenderStats};
+use crate::renderer::Renderer;
+use bevy_ecs::prelude::*;
+
+pub fn cull_chunks(
+    camera: Res<Camera>,
+    box_world: Res<BoxWorld>,
+    renderer: Res<Renderer>,
+    mut game_renderer: ResMut<GameRenderer>,
+    mut render_stats: ResMut<RenderStats>,
+) {
+    let frustum = Frustum::from_camera(&camera, renderer.render_context.aspect_ratio());
+    let visible_sections = box_world.get_visible_sections(&camera.render_position, |pos| {
+        let (min, max) = get_section_bounds(pos);
+        frustum.contains_aabb(&min, &max)
+    });
+    *render_stats = game_renderer.cull_chunks(
+        &renderer.render_context,
+        &frustum,
+        &camera.render_position,
+        visible_sections.as_ref(),
+    );
+}
@@ -1 +1,3 @@
+pub mod blocks;
+pub mod culling;
 pub mod wireframe;
@@ -90,6 +90,38 @@ impl Texture {
         })
     }
 
+    pub fn new_render_target(render_context: &RenderContext) -> Self {
+        let texture_size = Extent3d {
+            width: render_context.render_surface_config.width,
+            height: render_context.render_surface_config.height,
+            depth_or_array_layers: 1,
+        };
+        let format = render_context.render_surface_config.format;
+
+        let texture = render_context.device.create_texture(&TextureDescriptor {
+            label: Some("Create render target texture"),
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[format],
+        });
+
+        let texture_view = texture.create_view(&TextureViewDescriptor::default());
+
+        let sampler = render_context
+            .device
+            .create_sampler(&SamplerDescriptor::default());
+
+        Self {
+            texture,
+            texture_view,
+            sampler,
+        }
+    }
+
     pub fn new_depth(render_context: &RenderContext) -> Self {
         let texture_size = Extent3d {
             width: render_context.render_surface_config.width,
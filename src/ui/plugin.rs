@@ -1,5 +1,13 @@
+use crate::app::error::AppError;
+use crate::boxworld::plugin::WorldPlugin;
+use crate::boxworld::BoxWorld;
+use crate::game::camera::{Camera, CameraPlugin};
+use crate::game::player::{Player, PlayerPlugin};
 use crate::game::schedule::ScheduleStage;
-use crate::plugin::Plugin;
+use crate::game::systems::Time;
+use crate::plugin::{Plugin, RequiredResource};
+use crate::renderer::game_renderer::RenderStats;
+use crate::renderer::plugins::RendererPlugin;
 use crate::ui::systems::draw_ui::draw_ui;
 use crate::ui::systems::pre_update::pre_update;
 use crate::ui::UI;
@@ -8,14 +16,50 @@ use winit::window::Window;
 
 pub struct UIPlugin;
 
+impl UIPlugin {
+    pub const LABEL: &'static str = "ui";
+}
+
 impl Plugin for UIPlugin {
-    fn register_init(&self, world: &mut World, _schedule: &mut Schedule, window: &Window) {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[
+            CameraPlugin::LABEL,
+            PlayerPlugin::LABEL,
+            RendererPlugin::LABEL,
+            WorldPlugin::LABEL,
+        ]
+    }
+
+    fn required_resources(&self) -> Vec<RequiredResource> {
+        vec![
+            RequiredResource::of::<Camera>(),
+            RequiredResource::of::<Player>(),
+            RequiredResource::of::<Time>(),
+            RequiredResource::of::<RenderStats>(),
+            RequiredResource::of::<BoxWorld>(),
+        ]
+    }
+
+    fn register_init(
+        &self,
+        world: &mut World,
+        _schedule: &mut Schedule,
+        window: Option<&Window>,
+    ) -> Result<(), AppError> {
+        let window = window.ok_or(AppError::MissingWindow(Self::LABEL))?;
         world.insert_resource(UI::new(window));
+        Ok(())
     }
 
-    fn register_runtime(&self, _world: &mut World, schedule: &mut Schedule) {
-        schedule
-            .add_systems(pre_update.in_set(ScheduleStage::PreUpdate))
-            .add_systems(draw_ui.in_set(ScheduleStage::PreRender));
+    fn register_frame(&self, _world: &mut World, schedule: &mut Schedule) {
+        schedule.add_systems(
+            (pre_update, draw_ui)
+                .chain()
+                .in_set(ScheduleStage::PreRender),
+        );
     }
 }
@@ -28,7 +28,7 @@ impl DebugUIRenderer {
         &mut self,
         encoder: &mut CommandEncoder,
         render_context: &RenderContext,
-        output_view: &TextureView,
+        output_views: &[&TextureView],
         draw_data: UIDrawData,
     ) {
         self.egui_rpass
@@ -49,15 +49,17 @@ impl DebugUIRenderer {
             &draw_data.paint_jobs,
             &screen_descriptor,
         );
-        self.egui_rpass
-            .execute(
-                encoder,
-                output_view,
-                &draw_data.paint_jobs,
-                &screen_descriptor,
-                None,
-            )
-            .unwrap();
+        for output_view in output_views {
+            self.egui_rpass
+                .execute(
+                    encoder,
+                    output_view,
+                    &draw_data.paint_jobs,
+                    &screen_descriptor,
+                    None,
+                )
+                .unwrap();
+        }
         self.texture_delta = Some(draw_data.textures_delta);
     }
 
@@ -1,10 +1,18 @@
+use crate::boxworld::BoxWorld;
 use crate::game::camera::Camera;
 use crate::game::player::Player;
+use crate::renderer::game_renderer::RenderStats;
 use crate::ui::UI;
 use bevy_ecs::prelude::*;
 use egui::{Color32, Style, Visuals};
 
-pub fn draw_ui(ui: Res<UI>, player: Res<Player>, camera: Res<Camera>) {
+pub fn draw_ui(
+    ui: Res<UI>,
+    player: Res<Player>,
+    camera: Res<Camera>,
+    render_stats: Res<RenderStats>,
+    mut box_world: ResMut<BoxWorld>,
+) {
     let ctx = ui.context();
 
     let (camera_yaw, camera_pitch) = camera.get_yaw_pitch();
@@ -38,6 +46,30 @@ pub fn draw_ui(ui: Res<UI>, player: Res<Player>, camera: Res<Camera>) {
 
             ui.separator();
 
+            ui.label(format!(
+                "Chunks: {} drawn, {} culled",
+                render_stats.drawn_chunks, render_stats.culled_chunks
+            ));
+            ui.label(format!(
+                "Sections: {} drawn, {} hidden",
+                render_stats.drawn_sections, render_stats.hidden_sections
+            ));
+            ui.label(format!("Faces: {}", render_stats.drawn_faces));
+
+            ui.separator();
+
+            // Applied by the world on the next update, loading or unloading the chunks
+            let mut render_distance = box_world.get_render_distance();
+            let slider = egui::Slider::new(&mut render_distance, BoxWorld::RENDER_DISTANCE_RANGE)
+                .text("Render distance");
+            if ui.add(slider).changed() {
+                box_world.set_render_distance(render_distance);
+            }
+            ui.label(format!(
+                "Loaded chunks: {}",
+                box_world.get_loaded_chunk_count()
+            ));
+
             // Plot::new("FPS").show(ui, |plot_ui| {
             //     let l = self
             //         .resp
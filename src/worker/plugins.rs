@@ -1,3 +1,4 @@
+use crate::app::error::AppError;
 use crate::plugin::Plugin;
 use crate::worker::init_compute_task_pool;
 use bevy_ecs::prelude::*;
@@ -5,8 +6,22 @@ use winit::window::Window;
 
 pub struct WorkerPlugin;
 
+impl WorkerPlugin {
+    pub const LABEL: &'static str = "worker";
+}
+
 impl Plugin for WorkerPlugin {
-    fn register_init(&self, world: &mut World, _schedule: &mut Schedule, _window: &Window) {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn register_init(
+        &self,
+        world: &mut World,
+        _schedule: &mut Schedule,
+        _window: Option<&Window>,
+    ) -> Result<(), AppError> {
         // world.insert_resource(init_compute_task_pool());
+        Ok(())
     }
 }